@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use serde_json::{from_str, Value};
+use crate::config::Config;
+use crate::error::Error;
+use crate::runner::{Cmd, CommandRunner};
+
+use super::{NixList, Package, PackageBackend};
+
+/// Homebrew formulae (`homebrew.brews`) or casks (`homebrew.casks`).
+pub struct HomebrewBackend<'a> {
+    list: NixList,
+    runner: &'a dyn CommandRunner,
+    is_cask: bool,
+}
+
+impl<'a> HomebrewBackend<'a> {
+    pub fn new(config: &Config, runner: &'a dyn CommandRunner, is_cask: bool) -> Result<Self> {
+        let path = config.get_expanded_path(&config.homebrew_packages_path)?;
+        let attr = if is_cask { "homebrew.casks" } else { "homebrew.brews" };
+
+        Ok(Self {
+            list: NixList::new(path, attr, "", true),
+            runner,
+            is_cask,
+        })
+    }
+
+    /// Look up the versions of `names` with a single `brew info` call.
+    fn versions(&self, names: &[String]) -> Result<Vec<String>> {
+        let mut args = vec!["info", "--json=v2"];
+        if self.is_cask {
+            args.push("--cask");
+        }
+        args.extend(names.iter().map(String::as_str));
+
+        let output = self.runner.output(&Cmd::new("brew").args(&args))
+            .context("Failed to get package info")?;
+
+        let info: Value = if output.success() {
+            from_str(&output.stdout).unwrap_or(Value::Null)
+        } else {
+            Value::Null
+        };
+
+        let (list, name_key) = if self.is_cask {
+            (&info["casks"], "token")
+        } else {
+            (&info["formulae"], "name")
+        };
+
+        Ok(names.iter()
+            .map(|name| {
+                list.as_array()
+                    .and_then(|entries| entries.iter().find(|e| e[name_key] == name.as_str()))
+                    .and_then(|e| if self.is_cask { e["version"].as_str() } else { e["versions"]["stable"].as_str() })
+                    .unwrap_or("latest")
+                    .to_string()
+            })
+            .collect())
+    }
+}
+
+impl PackageBackend for HomebrewBackend<'_> {
+    fn id(&self) -> &'static str {
+        if self.is_cask { "cask" } else { "brew" }
+    }
+
+    fn describe(&self) -> &'static str {
+        if self.is_cask { "homebrew cask" } else { "homebrew formula" }
+    }
+
+    fn list_file(&self) -> &NixList {
+        &self.list
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<Package>> {
+        let mut args = vec!["search"];
+        if self.is_cask {
+            args.push("--cask");
+        }
+        args.push(query);
+
+        let output = self.runner.output(&Cmd::new("brew").args(&args))
+            .map_err(|e| Error::BackendUnavailable {
+                name: "brew".to_string(),
+                reason: e.to_string(),
+            })?;
+
+        let names: Vec<String> = output.stdout.lines()
+            .map(|line| line.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let versions = self.versions(&names)?;
+
+        Ok(names.into_iter()
+            .zip(versions)
+            .map(|(name, version)| Package {
+                attr: name.clone(),
+                name,
+                version,
+                source: self.id(),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,87 @@
+mod homebrew;
+mod nix;
+mod nix_list;
+
+use anyhow::Result;
+use crate::config::Config;
+use crate::runner::CommandRunner;
+
+pub use homebrew::HomebrewBackend;
+pub use nix::NixBackend;
+pub use nix_list::{normalize_entry, NixList};
+
+/// A package found by searching a backend.
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub name: String,
+    /// What to put in the package file. For nixpkgs this is the attribute
+    /// path relative to `pkgs`, e.g. `python312Packages.requests`.
+    pub attr: String,
+    pub version: String,
+    /// Id of the backend the package came from
+    pub source: &'static str,
+}
+
+/// A source of packages that is managed through a list in a Nix file.
+pub trait PackageBackend {
+    /// Short, stable identifier of the backend (e.g. `nixpkgs`).
+    fn id(&self) -> &'static str;
+
+    /// Human readable name used in prompts and listings.
+    fn describe(&self) -> &'static str;
+
+    /// The Nix file this backend edits.
+    fn list_file(&self) -> &NixList;
+
+    /// Search the package source for `query`.
+    fn search(&self, query: &str) -> Result<Vec<Package>>;
+
+    /// Check that `package` exists in the package source before it is added.
+    /// Backends that can't tell accept any package.
+    fn verify(&self, _package: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Names of the packages currently in the package file.
+    fn list(&self) -> Result<Vec<String>> {
+        self.list_file().names()
+    }
+
+    /// Whether `package` is present in the package file.
+    fn contains(&self, package: &str) -> Result<bool> {
+        self.list_file().contains(package)
+    }
+
+    /// Add `package` to the package file. Returns `false` if it was already present.
+    fn add(&self, package: &str) -> Result<bool> {
+        self.list_file().add(package)
+    }
+
+    /// Remove `package` from the package file. Returns `false` if it wasn't present.
+    fn remove(&self, package: &str) -> Result<bool> {
+        self.list_file().remove(package)
+    }
+
+    /// Check that the package file can be read and return the number of entries.
+    fn validate(&self) -> Result<usize> {
+        Ok(self.list_file().entries()?.len())
+    }
+}
+
+/// All backends available on the configured platform, in the order they
+/// should be queried.
+pub fn registered<'a>(config: &Config, runner: &'a dyn CommandRunner) -> Result<Vec<Box<dyn PackageBackend + 'a>>> {
+    let mut backends: Vec<Box<dyn PackageBackend + 'a>> = vec![Box::new(NixBackend::new(config, runner)?)];
+
+    if config.platform.has_homebrew() {
+        backends.push(Box::new(HomebrewBackend::new(config, runner, false)?));
+        backends.push(Box::new(HomebrewBackend::new(config, runner, true)?));
+    }
+
+    Ok(backends)
+}
+
+/// Look up a registered backend by its id.
+pub fn find<'a, 'r>(backends: &'a [Box<dyn PackageBackend + 'r>], id: &str) -> Option<&'a (dyn PackageBackend + 'r)> {
+    backends.iter().find(|b| b.id() == id).map(|b| b.as_ref())
+}
@@ -0,0 +1,245 @@
+use anyhow::{Context, Result};
+use serde_json::{from_str, Value};
+use std::path::PathBuf;
+use crate::config::Config;
+use crate::error::Error;
+use crate::flake;
+use crate::runner::{Cmd, CommandRunner, Output};
+
+use super::{normalize_entry, NixList, Package, PackageBackend};
+
+/// How many similarly named attributes to suggest for an unknown one.
+const SUGGESTIONS: usize = 3;
+
+/// Packages from nixpkgs, kept in `environment.systemPackages`.
+pub struct NixBackend<'a> {
+    list: NixList,
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> NixBackend<'a> {
+    pub fn new(config: &Config, runner: &'a dyn CommandRunner) -> Result<Self> {
+        Ok(Self {
+            list: NixList::new(config.packages_path()?, "environment.systemPackages", "with pkgs; ", false),
+            runner,
+        })
+    }
+
+    /// The flake whose `nixpkgs` input packages are checked against, see
+    /// [`flake::find_dir`].
+    fn flake_dir(&self) -> Option<PathBuf> {
+        flake::find_dir(self.list.path())
+    }
+
+    /// Run `nix eval` on `installable`, resolving `nixpkgs` to the flake's
+    /// pinned input if there is a flake.
+    fn eval(&self, installable: &str, extra: &[&str]) -> Result<Output> {
+        let mut cmd = Cmd::new("nix")
+            .args([
+                "--extra-experimental-features", "nix-command",
+                "--extra-experimental-features", "flakes",
+                "eval", installable,
+            ])
+            .args(extra);
+        if let Some(dir) = self.flake_dir() {
+            cmd = cmd.arg("--inputs-from").arg(dir.to_string_lossy());
+        }
+
+        self.runner.output(&cmd).map_err(|e| Error::BackendUnavailable {
+            name: "nix".to_string(),
+            reason: e.to_string(),
+        }.into())
+    }
+
+    /// Attributes next to `attr` with a name close to its last component,
+    /// best match first.
+    fn similar(&self, attr: &str) -> Vec<String> {
+        let (output, name) = match attr.rsplit_once('.') {
+            Some((parent, name)) => (
+                self.eval(&format!("nixpkgs#{}", parent), &["--json", "--apply", "builtins.attrNames"]),
+                name,
+            ),
+            None => (
+                self.eval("nixpkgs#legacyPackages", &[
+                    "--json", "--impure",
+                    "--apply", "ps: builtins.attrNames ps.${builtins.currentSystem}",
+                ]),
+                attr,
+            ),
+        };
+        let names: Vec<String> = match output {
+            Ok(output) if output.success() => from_str(&output.stdout).unwrap_or_default(),
+            _ => return Vec::new(),
+        };
+
+        let mut scored: Vec<(f64, String)> = names.into_iter()
+            .map(|candidate| (strsim::normalized_damerau_levenshtein(name, &candidate), candidate))
+            .filter(|(score, _)| *score >= 0.6)
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let prefix = attr.rsplit_once('.').map(|(parent, _)| format!("{}.", parent)).unwrap_or_default();
+        scored.into_iter()
+            .take(SUGGESTIONS)
+            .map(|(_, candidate)| format!("{}{}", prefix, candidate))
+            .collect()
+    }
+}
+
+impl PackageBackend for NixBackend<'_> {
+    fn id(&self) -> &'static str {
+        "nixpkgs"
+    }
+
+    fn describe(&self) -> &'static str {
+        "nixpkgs"
+    }
+
+    fn list_file(&self) -> &NixList {
+        &self.list
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<Package>> {
+        let packages_path = self.list.path();
+
+        let output = self.runner.output(&Cmd::new("nix")
+            .args([
+                "--extra-experimental-features", "nix-command",
+                "--extra-experimental-features", "flakes",
+                "search", "nixpkgs", query,
+                "--json"
+            ])
+            .current_dir(packages_path.parent().unwrap_or(packages_path)))
+            .map_err(|e| Error::BackendUnavailable {
+                name: "nix".to_string(),
+                reason: e.to_string(),
+            })?;
+
+        if !output.success() {
+            // nix exits with an error when nothing matches
+            if output.stderr.contains("no results") {
+                return Ok(Vec::new());
+            }
+            return Err(Error::BackendUnavailable {
+                name: "nix".to_string(),
+                reason: output.stderr.trim().to_string(),
+            }.into());
+        }
+
+        let json: Value = from_str(&output.stdout)
+            .context("Failed to parse JSON output")?;
+
+        let mut packages = Vec::new();
+
+        if let Value::Object(entries) = json {
+            for (key, value) in entries {
+                if let Value::Object(pkg) = value {
+                    if let (Some(Value::String(name)), Some(Value::String(version))) =
+                        (pkg.get("pname").or_else(|| pkg.get("name")), pkg.get("version")) {
+                        packages.push(Package {
+                            name: name.clone(),
+                            attr: attr_path(&key).to_string(),
+                            version: version.clone(),
+                            source: self.id(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(packages)
+    }
+
+    fn verify(&self, package: &str) -> Result<()> {
+        let attr = normalize_entry(package).unwrap_or_else(|| package.to_string());
+        let output = self.eval(&format!("nixpkgs#{}.name", attr), &["--raw"])?;
+        if output.success() {
+            return Ok(());
+        }
+
+        // Anything but a missing attribute (no network, evaluation errors in
+        // the flake) doesn't tell us whether the package exists
+        let missing = output.stderr.contains("does not provide attribute")
+            || output.stderr.contains("missing");
+        if !missing {
+            return Err(Error::BackendUnavailable {
+                name: "nix".to_string(),
+                reason: format!("could not evaluate {} (pass --no-verify to skip the check): {}",
+                    attr, output.stderr.trim()),
+            }.into());
+        }
+
+        Err(Error::UnknownPackage {
+            package: attr.clone(),
+            backend: self.describe().to_string(),
+            suggestions: self.similar(&attr),
+        }.into())
+    }
+}
+
+/// Attribute path relative to `pkgs` of a flake output key like
+/// `legacyPackages.x86_64-linux.python312Packages.requests`.
+fn attr_path(key: &str) -> &str {
+    match key.splitn(3, '.').collect::<Vec<_>>()[..] {
+        ["legacyPackages" | "packages", _system, attr] => attr,
+        _ => key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::FakeRunner;
+
+    const EVAL: &str = "nix --extra-experimental-features nix-command --extra-experimental-features flakes eval";
+
+    fn backend(runner: &FakeRunner) -> NixBackend<'_> {
+        let config = Config {
+            platform: crate::Platform::Linux,
+            linux_packages_path: "/nonexistent/apps.nix".to_string(),
+            ..Config::default()
+        };
+        NixBackend::new(&config, runner).unwrap()
+    }
+
+    #[test]
+    fn verifies_existing_attribute() {
+        let runner = FakeRunner::new();
+        runner.on(&format!("{} nixpkgs#python3Packages.requests.name", EVAL), Output::ok("python3.12-requests-2.32"));
+
+        backend(&runner).verify("pkgs.python3Packages.requests").unwrap();
+        assert_eq!(runner.command_lines(), [format!("{} nixpkgs#python3Packages.requests.name --raw", EVAL)]);
+    }
+
+    #[test]
+    fn suggests_similar_attributes() {
+        let runner = FakeRunner::new();
+        runner.on(EVAL, Output::err(1, "error: flake 'flake:nixpkgs' does not provide attribute 'ripgrp'"))
+            .on(&format!("{} nixpkgs#legacyPackages", EVAL), Output::ok(r#"["ripgrep","ripgrep-all","git"]"#));
+
+        let e = backend(&runner).verify("ripgrp").unwrap_err();
+        match e.downcast_ref::<Error>() {
+            Some(Error::UnknownPackage { package, suggestions, .. }) => {
+                assert_eq!(package, "ripgrp");
+                assert_eq!(suggestions, &["ripgrep"]);
+            },
+            _ => panic!("unexpected error: {:#}", e),
+        }
+    }
+
+    #[test]
+    fn unavailable_nix_is_not_an_unknown_package() {
+        let runner = FakeRunner::new();
+        runner.on(EVAL, Output::err(1, "error: unable to download"));
+
+        let e = backend(&runner).verify("ripgrep").unwrap_err();
+        assert_eq!(Error::exit_code_of(&e), 8);
+    }
+
+    #[test]
+    fn strips_system_from_search_results() {
+        assert_eq!(attr_path("legacyPackages.x86_64-linux.python312Packages.requests"), "python312Packages.requests");
+        assert_eq!(attr_path("packages.aarch64-darwin.hello"), "hello");
+        assert_eq!(attr_path("hello"), "hello");
+    }
+}
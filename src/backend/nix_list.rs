@@ -0,0 +1,414 @@
+use anyhow::{Context, Result};
+use nix_editor::{read, write};
+use rnix::{SyntaxKind, SyntaxNode};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use crate::{backup, diff};
+use crate::error::Error;
+
+/// A list attribute (e.g. `environment.systemPackages`) inside a Nix file
+/// that holds package entries.
+#[derive(Debug, Clone)]
+pub struct NixList {
+    path: PathBuf,
+    attr: &'static str,
+    scope: &'static str,
+    quoted: bool,
+}
+
+impl NixList {
+    /// `scope` is written in front of the list when the attribute has to be
+    /// created (e.g. `with pkgs; `), `quoted` controls whether entries are
+    /// string literals.
+    pub fn new(path: PathBuf, attr: &'static str, scope: &'static str, quoted: bool) -> Self {
+        Self { path, attr, scope, quoted }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn attr(&self) -> &'static str {
+        self.attr
+    }
+
+    /// Current contents of the file.
+    pub fn read(&self) -> Result<String> {
+        if !self.path.exists() {
+            return Err(Error::PackageFileMissing(self.path.clone()).into());
+        }
+        fs::read_to_string(&self.path).context("Failed to read configuration file")
+    }
+
+    /// Raw entries as they appear in the file. A missing attribute is treated
+    /// as an empty list.
+    pub fn entries(&self) -> Result<Vec<String>> {
+        self.entries_in(&self.read()?)
+    }
+
+    fn entries_in(&self, content: &str) -> Result<Vec<String>> {
+        match read::getarrvals(content, self.attr) {
+            Ok(entries) => Ok(entries),
+            Err(read::ReadError::NoAttr) => Ok(Vec::new()),
+            Err(e) => Err(self.read_error(e)),
+        }
+    }
+
+    fn read_error(&self, error: read::ReadError) -> anyhow::Error {
+        match error {
+            read::ReadError::ParseError => anyhow::anyhow!("Failed to parse {}", self.path.display()),
+            _ => Error::AttributeNotFound {
+                attr: self.attr.to_string(),
+                file: self.path.clone(),
+            }.into(),
+        }
+    }
+
+    /// Normalized package names of all entries, see [`normalize_entry`].
+    pub fn names(&self) -> Result<Vec<String>> {
+        Ok(self.entries()?
+            .iter()
+            .filter_map(|p| normalize_entry(p))
+            .collect())
+    }
+
+    /// Whether an entry refers to exactly `package`.
+    pub fn contains(&self, package: &str) -> Result<bool> {
+        self.contains_in(&self.read()?, package)
+    }
+
+    fn contains_in(&self, content: &str, package: &str) -> Result<bool> {
+        let package = normalize_entry(package);
+        Ok(self.entries_in(content)?
+            .iter()
+            .any(|p| package.is_some() && normalize_entry(p) == package))
+    }
+
+    /// Adds `package` to the list, creating the attribute if needed. Returns
+    /// `false` if the package was already present.
+    pub fn add(&self, package: &str) -> Result<bool> {
+        let content = self.read()?;
+        match self.with_added(&content, package)? {
+            Some(new_content) => self.save(&content, &new_content).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// `content` of the file with `package` added to the list, or `None` if
+    /// it is already present. The edit is checked like [`Self::add`] would.
+    pub fn with_added(&self, content: &str, package: &str) -> Result<Option<String>> {
+        if self.contains_in(content, package)? {
+            return Ok(None);
+        }
+
+        let package = normalize_entry(package)
+            .ok_or_else(|| anyhow::anyhow!("Invalid package name '{}'", package))?;
+        let entry = if self.quoted {
+            format!("\"{}\"", package)
+        } else if self.in_pkgs_scope(content) {
+            package
+        } else {
+            format!("pkgs.{}", package)
+        };
+
+        let (new_content, mut expected) = match read::getarrvals(content, self.attr) {
+            Ok(entries) => (
+                write::addtoarr(content, self.attr, vec![entry.clone()])
+                    .map_err(|e| anyhow::anyhow!("Failed to add package to array: {}", e))?,
+                entries,
+            ),
+            Err(read::ReadError::NoAttr) => {
+                let initial_value = format!("{}[
+    {}
+  ]", self.scope, entry);
+
+                (
+                    write::write(content, self.attr, &initial_value)
+                        .map_err(|e| anyhow::anyhow!("Failed to initialize {}: {}", self.attr, e))?,
+                    Vec::new(),
+                )
+            }
+            Err(e) => return Err(self.read_error(e)),
+        };
+        expected.push(entry);
+
+        self.check(content, &new_content, &expected)?;
+        Ok(Some(new_content))
+    }
+
+    /// Whether entries of the list are resolved in `pkgs`, i.e. the list is
+    /// inside `with pkgs;` or will be created that way.
+    fn in_pkgs_scope(&self, content: &str) -> bool {
+        match read::getarrvals(content, self.attr) {
+            Err(read::ReadError::NoAttr) => self.scope.contains("with pkgs;"),
+            _ => read::getwithvalue(content, self.attr)
+                .is_ok_and(|scopes| scopes.iter().any(|scope| scope == "pkgs")),
+        }
+    }
+
+    /// Removes `package` from the list. Returns `false` if it wasn't present.
+    pub fn remove(&self, package: &str) -> Result<bool> {
+        let content = self.read()?;
+        match self.with_removed(&content, package)? {
+            Some(new_content) => self.save(&content, &new_content).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// `content` of the file with `package` removed from the list, or `None`
+    /// if it isn't present. The edit is checked like [`Self::remove`] would.
+    pub fn with_removed(&self, content: &str, package: &str) -> Result<Option<String>> {
+        let entries = match read::getarrvals(content, self.attr) {
+            Ok(entries) => entries,
+            Err(read::ReadError::NoAttr) => return Ok(None),
+            Err(e) => return Err(self.read_error(e)),
+        };
+
+        // Remove the entry exactly as it is written in the file
+        let normalized = normalize_entry(package);
+        let Some(index) = entries.iter()
+            .position(|p| normalized.is_some() && normalize_entry(p) == normalized) else {
+            return Ok(None);
+        };
+        let mut expected = entries;
+        let entry = expected.remove(index);
+
+        let new_content = write::rmarr(content, self.attr, vec![entry])
+            .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", package, e))?;
+
+        self.check(content, &new_content, &expected)?;
+        Ok(Some(new_content))
+    }
+
+    /// Write `new` over `old`, keeping `old` as a backup.
+    fn save(&self, old: &str, new: &str) -> Result<()> {
+        backup::replace(&self.path, &backup::new_name(), old, new)
+    }
+
+    /// Check that `new` is valid Nix, that the list holds exactly the
+    /// `expected` entries and that nothing outside the list changed.
+    fn check(&self, old: &str, new: &str, expected: &[String]) -> Result<()> {
+        self.find_problem(old, new, expected).map_err(|reason| Error::InvalidEdit {
+            file: self.path.clone(),
+            reason,
+            diff: diff::unified(&self.path, old, new),
+        }.into())
+    }
+
+    fn find_problem(&self, old: &str, new: &str, expected: &[String]) -> std::result::Result<(), String> {
+        let parsed = rnix::Root::parse(new);
+        if let Some(error) = parsed.errors().first() {
+            return Err(format!("the edit produces invalid Nix ({})", error));
+        }
+
+        let mut entries: Vec<_> = read::getarrvals(new, self.attr)
+            .map_err(|_| format!("{} can't be read back after the edit", self.attr))?
+            .iter()
+            .filter_map(|entry| normalize_entry(entry))
+            .collect();
+        let mut expected: Vec<_> = expected.iter().filter_map(|entry| normalize_entry(entry)).collect();
+        entries.sort();
+        expected.sort();
+        if entries != expected {
+            return Err(format!("{} would contain {:?} instead of {:?}", self.attr, entries, expected));
+        }
+
+        if self.tokens_outside_list(&rnix::Root::parse(old).syntax())
+            != self.tokens_outside_list(&parsed.syntax()) {
+            return Err(format!("the edit changes more than {}", self.attr));
+        }
+        Ok(())
+    }
+
+    /// The non-whitespace tokens of the file outside the `attr = [ ... ];`
+    /// binding of the list.
+    fn tokens_outside_list(&self, root: &SyntaxNode) -> Vec<String> {
+        let binding = root.descendants()
+            .filter(|node| node.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+            .find(|node| attr_path(node) == self.attr)
+            .map(|node| node.text_range());
+
+        root.descendants_with_tokens()
+            .filter_map(|element| element.into_token())
+            .filter(|token| token.kind() != SyntaxKind::TOKEN_WHITESPACE)
+            .filter(|token| binding.is_none_or(|range| !range.contains_range(token.text_range())))
+            .map(|token| token.text().to_string())
+            .collect()
+    }
+}
+
+/// Full attribute path bound by an `attrpath = value;` node, including the
+/// paths of enclosing bindings (`environment = { systemPackages = ...; }`).
+fn attr_path(binding: &SyntaxNode) -> String {
+    let mut parts: Vec<String> = binding.ancestors()
+        .filter(|node| node.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+        .filter_map(|node| node.children().find(|child| child.kind() == SyntaxKind::NODE_ATTRPATH))
+        .map(|path| path.text().to_string().split_whitespace().collect())
+        .collect();
+    parts.reverse();
+    parts.join(".")
+}
+
+/// Remove `# ...` and `/* ... */` comments from `text`, leaving string
+/// literals alone.
+fn strip_comments(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_string = !in_string;
+                result.push(c);
+            },
+            '\\' if in_string => {
+                result.push(c);
+                result.extend(chars.next());
+            },
+            '#' if !in_string => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        result.push(c);
+                        break;
+                    }
+                }
+            },
+            '/' if !in_string && chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                result.push(' ');
+            },
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// The attribute path a list entry (or a package name given by the user)
+/// refers to, normalized so entries can be compared exactly: comments,
+/// whitespace, quotes and a leading `pkgs.` are removed. `None` if nothing is
+/// left, e.g. for an entry that is only a comment.
+pub fn normalize_entry(entry: &str) -> Option<String> {
+    let entry: String = strip_comments(entry)
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let entry = entry.trim_end_matches([';', ']', '}']).trim_matches('"');
+    let entry = entry.strip_prefix("pkgs.").unwrap_or(entry);
+
+    (!entry.is_empty()).then(|| entry.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APPS: &str = r#"{ pkgs, ... }:
+{
+  environment.systemPackages = with pkgs; [
+    git
+    # editors
+    vim # the one true editor
+  ];
+}
+"#;
+
+    fn list() -> NixList {
+        NixList::new(PathBuf::from("apps.nix"), "environment.systemPackages", "with pkgs; ", false)
+    }
+
+    fn entries(content: &str) -> Vec<String> {
+        let mut names: Vec<String> = read::getarrvals(content, "environment.systemPackages").unwrap()
+            .iter()
+            .filter_map(|entry| normalize_entry(entry))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn normalizes_entries() {
+        assert_eq!(normalize_entry("pkgs.ripgrep").as_deref(), Some("ripgrep"));
+        assert_eq!(normalize_entry("  vim # editor\n").as_deref(), Some("vim"));
+        assert_eq!(normalize_entry("\"wget\"").as_deref(), Some("wget"));
+        assert_eq!(normalize_entry("python3Packages.requests /* http */").as_deref(), Some("python3Packages.requests"));
+        assert_eq!(normalize_entry("\"a#b\"").as_deref(), Some("a#b"));
+        assert_eq!(normalize_entry("# only a comment"), None);
+        assert_eq!(normalize_entry(""), None);
+    }
+
+    #[test]
+    fn adds_in_pkgs_scope() {
+        let new = list().with_added(APPS, "ripgrep").unwrap().unwrap();
+        assert_eq!(entries(&new), ["git", "ripgrep", "vim"]);
+        assert!(new.contains("    ripgrep\n"));
+        assert!(new.contains("# editors"));
+    }
+
+    #[test]
+    fn adds_with_pkgs_prefix_outside_pkgs_scope() {
+        let content = "{ pkgs, ... }:\n{\n  environment.systemPackages = [\n    pkgs.git\n  ];\n}\n";
+        let new = list().with_added(content, "ripgrep").unwrap().unwrap();
+        assert!(new.contains("pkgs.ripgrep"));
+    }
+
+    #[test]
+    fn creates_missing_list() {
+        let content = "{ pkgs, ... }:\n{\n  networking.hostName = \"web\";\n}\n";
+        let new = list().with_added(content, "git").unwrap().unwrap();
+        assert!(new.contains("environment.systemPackages = with pkgs; ["));
+        assert_eq!(entries(&new), ["git"]);
+        assert!(new.contains("networking.hostName = \"web\";"));
+    }
+
+    #[test]
+    fn adding_present_package_changes_nothing() {
+        assert_eq!(list().with_added(APPS, "vim").unwrap(), None);
+        assert_eq!(list().with_added(APPS, "pkgs.git").unwrap(), None);
+    }
+
+    #[test]
+    fn removes_entry_with_comment() {
+        let new = list().with_removed(APPS, "vim").unwrap().unwrap();
+        assert_eq!(entries(&new), ["git"]);
+        assert!(!new.contains("vim"));
+    }
+
+    #[test]
+    fn removing_missing_package_changes_nothing() {
+        assert_eq!(list().with_removed(APPS, "emacs").unwrap(), None);
+        assert_eq!(list().with_removed("{ }\n", "git").unwrap(), None);
+    }
+
+    #[test]
+    fn finds_problems_in_edits() {
+        let list = list();
+        let expected = ["git".to_string(), "vim".to_string()];
+        assert_eq!(list.find_problem(APPS, APPS, &expected), Ok(()));
+
+        let broken = APPS.replace("];", "]");
+        assert!(list.find_problem(APPS, &broken, &expected).unwrap_err().contains("invalid Nix"));
+
+        let missing = APPS.replace("    git\n", "");
+        assert!(list.find_problem(APPS, &missing, &expected).unwrap_err().contains("instead of"));
+
+        let outside = APPS.replace("{ pkgs, ... }:", "{ pkgs, lib, ... }:");
+        assert!(list.find_problem(APPS, &outside, &expected).unwrap_err().contains("changes more than"));
+    }
+
+    #[test]
+    fn refuses_invalid_edit() {
+        let e = list().check(APPS, &APPS.replace("git", "gti"), &["git".to_string(), "vim".to_string()]).unwrap_err();
+        assert_eq!(Error::exit_code_of(&e), 12);
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDateTime};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use crate::config::Config;
+
+/// How many backups are kept per package file. Older ones are deleted when a
+/// new backup is made.
+pub const KEEP: usize = 20;
+
+/// Format of backup names, which sort chronologically.
+const NAME_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+
+/// A saved copy of a package file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    /// Name to pass to `yuki restore`. Backups of the files changed together
+    /// share it, see [`new_name`].
+    pub name: String,
+    /// The package file it is a copy of
+    pub file: PathBuf,
+    pub path: PathBuf,
+}
+
+impl Backup {
+    /// When the backup was made, if the name is one yuki created.
+    pub fn time(&self) -> Option<NaiveDateTime> {
+        NaiveDateTime::parse_from_str(&self.name, NAME_FORMAT).ok()
+    }
+}
+
+/// Write `content` to `path` through a temporary file in the same directory
+/// that is renamed over it, so the file is never left half written. If
+/// `path` is a symlink, its target is replaced.
+pub fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = path.parent()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine directory of {}", path.display()))?;
+
+    let mut temp = tempfile::Builder::new()
+        .prefix(".yuki")
+        .tempfile_in(dir)
+        .with_context(|| format!("Failed to create a temporary file in {}", dir.display()))?;
+    temp.write_all(content.as_bytes())
+        .and_then(|_| temp.as_file().sync_all())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    if let Ok(metadata) = fs::metadata(&path) {
+        fs::set_permissions(temp.path(), metadata.permissions())?;
+    }
+
+    temp.persist(&path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+/// A name for the backups of one change, unique as long as changes don't run
+/// concurrently.
+pub fn new_name() -> String {
+    Local::now().format(NAME_FORMAT).to_string()
+}
+
+/// Replace the contents of `file`, `old`, with `new`, keeping `old` as the
+/// backup `name`.
+pub fn replace(file: &Path, name: &str, old: &str, new: &str) -> Result<()> {
+    create(file, name, old)?;
+    write_atomic(file, new)
+        .with_context(|| format!("Failed to write {}", file.display()))
+}
+
+/// Directory holding the backups of `file`.
+fn dir_of(file: &Path) -> Result<PathBuf> {
+    let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    let name = file.to_string_lossy().trim_start_matches('/').replace('/', "%");
+    Ok(Config::get_state_dir()?.join("backups").join(name))
+}
+
+/// Save `content` as the newest backup of `file`, called `name`, deleting
+/// the oldest ones beyond [`KEEP`].
+pub fn create(file: &Path, name: &str, content: &str) -> Result<Backup> {
+    let dir = dir_of(file)?;
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let backup = Backup {
+        path: dir.join(format!("{}.nix", name)),
+        name: name.to_string(),
+        file: file.to_path_buf(),
+    };
+    write_atomic(&backup.path, content)
+        .with_context(|| format!("Failed to back up {}", file.display()))?;
+
+    let backups = list(file)?;
+    for old in backups.iter().skip(KEEP) {
+        fs::remove_file(&old.path)
+            .with_context(|| format!("Failed to remove old backup {}", old.path.display()))?;
+    }
+
+    Ok(backup)
+}
+
+/// Backups of `file`, newest first.
+pub fn list(file: &Path) -> Result<Vec<Backup>> {
+    let dir = dir_of(file)?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".nix")) {
+            backups.push(Backup {
+                name: name.to_string(),
+                file: file.to_path_buf(),
+                path: path.clone(),
+            });
+        }
+    }
+
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+/// Replace the package file with the contents of `backup`. The current
+/// contents are backed up first as `name`, so a restore can be undone as
+/// well. Returns `false` if the file already matched the backup.
+pub fn restore(backup: &Backup, name: &str) -> Result<bool> {
+    let content = fs::read_to_string(&backup.path)
+        .with_context(|| format!("Failed to read backup {}", backup.path.display()))?;
+    if let Ok(current) = fs::read_to_string(&backup.file) {
+        if current == content {
+            return Ok(false);
+        }
+        create(&backup.file, name, &current)?;
+    }
+
+    write_atomic(&backup.file, &content)?;
+    Ok(true)
+}
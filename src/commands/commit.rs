@@ -0,0 +1,284 @@
+use anyhow::{Context, Result};
+use colored::*;
+use std::path::{Path, PathBuf};
+use yuki::git::{self, Commit, Pull};
+use yuki::runner::{Cmd, CommandRunner};
+use yuki::{Config, Edit, Error, MessageContext};
+
+/// Placeholder values for the commit of `edits`, whose files are in `repo`.
+/// Sources and files that differ between the edits are joined by commas.
+pub fn edit_context(config: &Config, repo: &Path, edits: &[Edit], version: &str) -> MessageContext {
+    let mut sources: Vec<String> = Vec::new();
+    let mut files: Vec<String> = Vec::new();
+    for edit in edits {
+        let file = edit.file.canonicalize().unwrap_or_else(|_| edit.file.clone());
+        let file = file.strip_prefix(repo).unwrap_or(&file).display().to_string();
+        if !files.contains(&file) {
+            files.push(file);
+        }
+        if !sources.iter().any(|source| source == edit.source) {
+            sources.push(edit.source.to_string());
+        }
+    }
+
+    MessageContext {
+        packages: edits.iter().map(|edit| edit.package.clone()).collect(),
+        source: sources.join(", "),
+        host: config.host_name(),
+        file: files.join(", "),
+        version: version.to_string(),
+        inputs: Vec::new(),
+    }
+}
+
+/// Bring the repository holding `file` up to date with its upstream if
+/// `auto_pull` is enabled.
+pub fn pull_changes(config: &Config, file: &Path) -> Result<()> {
+    if !config.auto_pull {
+        return Ok(());
+    }
+
+    let repo = config.repo_root(file)?;
+    match git::pull(&repo, config.pull_rebase)? {
+        Pull::UpToDate => {},
+        Pull::FastForwarded => println!("⬇️  Pulled remote changes"),
+        Pull::Rebased => println!("⬇️  Rebased local commits onto remote changes"),
+        Pull::NoUpstream => println!("⚠️  Not pulling, the current branch has no upstream"),
+    }
+    Ok(())
+}
+
+/// Commit `files` in `repo` with the message `template` filled from `context`. The commit is pushed if
+/// `auto_push` is enabled, or goes to a review branch if `review_branch` is.
+pub fn commit_changes(config: &Config, runner: &dyn CommandRunner, repo: &Path, files: &[PathBuf], context: &MessageContext, template: &str) -> Result<()> {
+    let message = context.render(template);
+    if config.review_branch {
+        return commit_for_review(config, runner, repo, files, context, &message);
+    }
+
+    if git::commit_files(runner, repo, files, &message)? == Commit::NothingToCommit {
+        println!("📝 Nothing to commit");
+        return Ok(());
+    }
+    println!("📝 Changes committed to git");
+
+    if config.auto_push {
+        match git::push(repo) {
+            Ok(()) => println!("🚀 Changes pushed to remote"),
+            Err(e) if !config.push_failure_fatal => {
+                eprintln!("⚠️  Commit was only made locally: {:#}", e);
+            },
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Quote `arg` for a POSIX shell if needed.
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:@+".contains(c)) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// The git command equivalent to what [`pull_changes`] would do, for
+/// `--dry-run`.
+pub fn pull_plan(config: &Config) -> Option<String> {
+    config.auto_pull.then(|| {
+        if config.pull_rebase { "git pull --rebase" } else { "git pull --ff-only" }.to_string()
+    })
+}
+
+/// The git commands equivalent to what [`commit_changes`] would do, for
+/// `--dry-run`.
+pub fn commit_plan(config: &Config, repo: &Path, files: &[PathBuf], context: &MessageContext, template: &str) -> Result<Vec<String>> {
+    let mut plan = Vec::new();
+
+    let paths: Vec<String> = files.iter()
+        .map(|file| {
+            let file = file.canonicalize().unwrap_or_else(|_| file.clone());
+            shell_quote(&file.strip_prefix(repo).unwrap_or(&file).display().to_string())
+        })
+        .collect();
+    let commit = format!("git commit -m {} -- {}", shell_quote(&context.render(template)), paths.join(" "));
+
+    if config.review_branch {
+        let base = git::current_branch(repo)?;
+        let branch = git::new_branch_name(repo, &context.render(&config.review_branch_name))?;
+        plan.push(format!("git switch -c {}", branch));
+        plan.push(commit);
+        plan.push(format!("git switch {}", base));
+        if config.auto_push {
+            plan.push(format!("git push origin {}", branch));
+        }
+        if config.auto_push && !config.review_command.is_empty() {
+            plan.push(context.render(&config.review_command
+                .replace("<branch>", &branch)
+                .replace("<base>", &base)));
+        }
+    } else {
+        plan.push(commit);
+        if config.auto_push {
+            plan.push("git push".to_string());
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Commit to a new branch and switch back to the original branch. If
+/// `auto_push` is enabled, the branch is then pushed and the review command
+/// run.
+fn commit_for_review(config: &Config, runner: &dyn CommandRunner, repo: &Path, files: &[PathBuf], context: &MessageContext, message: &str) -> Result<()> {
+    let base = git::current_branch(repo)?;
+    let branch = git::new_branch_name(repo, &context.render(&config.review_branch_name))?;
+
+    git::create_branch(repo, &branch)?;
+    let committed = git::commit_files(runner, repo, files, message);
+    let created = matches!(committed, Ok(Commit::Created));
+    // Only the committed files are reset to their original state on the way back
+    git::switch_branch(repo, &base, if created { files } else { &[] })?;
+
+    if !created {
+        git::delete_branch(repo, &branch)?;
+        committed?;
+        println!("📝 Nothing to commit");
+        return Ok(());
+    }
+    println!("📝 Changes committed to branch {}", branch.bright_blue());
+    println!("↩️  Switched back to {}, the changes land there once {} is merged", base, branch);
+
+    if !config.auto_push {
+        println!("📝 {} was not pushed, auto_push is off", branch);
+        return Ok(());
+    }
+
+    if let Err(e) = git::push_branch(repo, &branch, &base) {
+        if config.push_failure_fatal {
+            return Err(e);
+        }
+        eprintln!("⚠️  {} was only created locally: {:#}", branch, e);
+        return Ok(());
+    }
+    println!("🚀 Pushed {} for review", branch.bright_blue());
+
+    if !config.review_command.is_empty() {
+        let command = context.render(&config.review_command
+            .replace("<branch>", &branch)
+            .replace("<base>", &base));
+        println!("🔄 Running review command: {}", command.bright_blue());
+
+        let output = runner.stream(&Cmd::shell(&command).current_dir(repo))
+            .with_context(|| Error::HookFailed {
+                command: command.clone(),
+                status: -1,
+            })?;
+
+        if !output.success() {
+            return Err(Error::HookFailed {
+                command,
+                status: output.status,
+            }.into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use yuki::runner::FakeRunner;
+
+    use crate::commands::testing;
+
+    /// A repository with one commit on `main`, tracking a bare `origin` next
+    /// to it.
+    fn repo_with_remote() -> (tempfile::TempDir, PathBuf, PathBuf) {
+        let (dir, repo) = testing::repo(&[("apps.nix", "[ git ]\n")]);
+        let remote = dir.path().join("remote.git");
+        git2::Repository::init_bare(&remote).unwrap();
+
+        let git = git2::Repository::open(&repo).unwrap();
+        git.config().unwrap().set_str("branch.main.remote", "origin").unwrap();
+        git.remote("origin", remote.to_str().unwrap()).unwrap();
+        git::push(&repo).unwrap();
+        (dir, repo, remote)
+    }
+
+    fn branch_commit(repo: &Path, branch: &str) -> Option<String> {
+        let git = git2::Repository::open(repo).unwrap();
+        let branch = git.find_branch(branch, git2::BranchType::Local).ok()?;
+        let commit = branch.get().peel_to_commit().unwrap();
+        Some(commit.summary().unwrap().to_string())
+    }
+
+    fn review_config(auto_push: bool) -> Config {
+        Config {
+            review_branch: true,
+            auto_push,
+            review_branch_name: "yuki/<packages>".to_string(),
+            review_command: "open-review <branch> <base>".to_string(),
+            ..Config::default()
+        }
+    }
+
+    fn context() -> MessageContext {
+        MessageContext {
+            packages: vec!["ripgrep".to_string()],
+            ..MessageContext::default()
+        }
+    }
+
+    #[test]
+    fn review_branch_is_pushed_and_leaves_other_files_alone() {
+        let (_dir, repo, remote) = repo_with_remote();
+        let runner = FakeRunner::new();
+        fs::write(repo.join("apps.nix"), "[ git ripgrep ]\n").unwrap();
+        fs::write(repo.join("notes.txt"), "work in progress\n").unwrap();
+
+        commit_changes(&review_config(true), &runner, &repo, &[repo.join("apps.nix")], &context(), "installed <package>").unwrap();
+
+        assert_eq!(git::current_branch(&repo).unwrap(), "main");
+        assert_eq!(branch_commit(&repo, "main").as_deref(), Some("init"));
+        assert_eq!(branch_commit(&remote, "yuki/ripgrep").as_deref(), Some("installed ripgrep"));
+        // The edit lives on the review branch only, unrelated work stays put
+        assert_eq!(fs::read_to_string(repo.join("apps.nix")).unwrap(), "[ git ]\n");
+        assert_eq!(fs::read_to_string(repo.join("notes.txt")).unwrap(), "work in progress\n");
+        assert_eq!(git::changed_files(&repo, &[]).unwrap(), vec![PathBuf::from("notes.txt")]);
+        assert_eq!(runner.command_lines(), vec!["sh -c open-review yuki/ripgrep main"]);
+    }
+
+    #[test]
+    fn review_branch_stays_local_without_auto_push() {
+        let (_dir, repo, remote) = repo_with_remote();
+        let runner = FakeRunner::new();
+        fs::write(repo.join("apps.nix"), "[ git ripgrep ]\n").unwrap();
+
+        commit_changes(&review_config(false), &runner, &repo, &[repo.join("apps.nix")], &context(), "installed <package>").unwrap();
+
+        assert_eq!(branch_commit(&repo, "yuki/ripgrep").as_deref(), Some("installed ripgrep"));
+        assert_eq!(branch_commit(&remote, "yuki/ripgrep"), None);
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    fn review_plan_only_pushes_with_auto_push() {
+        let (_dir, repo, _remote) = repo_with_remote();
+        let files = [repo.join("apps.nix")];
+
+        let plan = commit_plan(&review_config(false), &repo, &files, &context(), "installed <package>").unwrap();
+        assert_eq!(plan, vec![
+            "git switch -c yuki/ripgrep",
+            "git commit -m 'installed ripgrep' -- apps.nix",
+            "git switch main",
+        ]);
+
+        let plan = commit_plan(&review_config(true), &repo, &files, &context(), "installed <package>").unwrap();
+        assert_eq!(&plan[3..], ["git push origin yuki/ripgrep", "open-review yuki/ripgrep main"]);
+    }
+}
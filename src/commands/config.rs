@@ -0,0 +1,94 @@
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use colored::*;
+use std::path::Path;
+use yuki::config::settings;
+use yuki::runner::{Cmd, CommandRunner};
+use yuki::Config;
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Print the value of a setting
+    Get {
+        key: String,
+    },
+    /// Change a setting
+    Set {
+        key: String,
+        value: String,
+    },
+    /// Reset a setting to its default
+    Unset {
+        key: String,
+    },
+    /// Show all settings and where their values come from
+    List,
+    /// Print the path of the config file
+    Path,
+    /// Open the config file in $VISUAL or $EDITOR
+    Edit,
+}
+
+fn display_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+pub fn run_config(runner: &dyn CommandRunner, config_path: Option<&Path>, command: ConfigCommand) -> Result<()> {
+    let path = Config::ensure_config(config_path)?;
+
+    match command {
+        ConfigCommand::Get { key } => {
+            println!("{}", display_value(&settings::get(&path, &key)?.value));
+        },
+        ConfigCommand::Set { key, value } => {
+            settings::set(&path, &key, &value)?;
+            println!("✨ Set {} to {}", key.bright_blue(), value.green());
+        },
+        ConfigCommand::Unset { key } => {
+            settings::unset(&path, &key)?;
+            let setting = settings::get(&path, &key)?;
+            println!("✨ Reset {} to {}", key.bright_blue(), display_value(&setting.value).green());
+        },
+        ConfigCommand::List => {
+            let settings = settings::list(&path)?;
+            let width = settings.iter().map(|s| s.key.len()).max().unwrap_or(0);
+            for setting in settings {
+                println!(
+                    "{:width$}  {}  {}",
+                    setting.key,
+                    display_value(&setting.value),
+                    format!("({})", setting.source).dimmed(),
+                    width = width
+                );
+            }
+        },
+        ConfigCommand::Path => {
+            println!("{}", path.display());
+        },
+        ConfigCommand::Edit => {
+            let editor = std::env::var("VISUAL")
+                .or_else(|_| std::env::var("EDITOR"))
+                .unwrap_or_else(|_| "vi".to_string());
+
+            // Let the shell split the editor command so e.g. `code --wait` works
+            let output = runner.interactive(&Cmd::new("sh").args([
+                "-c",
+                &format!("{} \"$1\"", editor),
+                "sh",
+                &path.to_string_lossy(),
+            ]))?;
+            if !output.success() {
+                return Err(anyhow::anyhow!("{} exited with status {}", editor, output.status));
+            }
+
+            Config::load_from(&path)
+                .context("The edited config is invalid, run `yuki config edit` to fix it")?;
+            println!("✨ Config saved");
+        },
+    }
+
+    Ok(())
+}
@@ -1,25 +1,27 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use colored::*;
-use std::{fs, process::Command, os::unix::fs::MetadataExt};
-use crate::config::Config;
+use std::os::unix::fs::MetadataExt;
+use yuki::{backend, git};
+use yuki::runner::{Cmd, CommandRunner};
+use yuki::Config;
 
-pub fn check_doctor(config: &Config) -> Result<()> {
+pub fn check_doctor(config: &Config, runner: &dyn CommandRunner) -> Result<()> {
     println!("{}", "==> Checking configuration...".bright_blue());
     
     // Check config file paths and permissions
     check_config_paths(config)?;
     
     // Check config file contents
-    check_config_contents(config)?;
+    check_config_contents(config, runner)?;
     
     // Check required commands
-    check_commands()?;
+    check_commands(runner)?;
     
     // Check git repository
     check_git_repo(config)?;
     
     // Test search functionality
-    check_search(config)?;
+    check_search(config, runner)?;
 
     println!("\n{}", "Everything looks good! 🎉".green());
     Ok(())
@@ -27,6 +29,11 @@ pub fn check_doctor(config: &Config) -> Result<()> {
 
 fn check_config_paths(config: &Config) -> Result<()> {
     println!("\n{}", "Checking configuration paths and permissions:".bright_blue());
+    println!("Platform: {}", config.platform.to_string().bright_blue());
+    match &config.host {
+        Some(host) => println!("Host profile: {}", host.bright_blue()),
+        None => println!("Host profile: {}", "none".dimmed()),
+    }
     
     // Check Linux packages path
     let linux_path = config.get_expanded_path(&config.linux_packages_path)?;
@@ -83,68 +90,17 @@ fn check_file_permissions(path: &std::path::Path, file_type: &str) {
     }
 }
 
-fn check_config_contents(config: &Config) -> Result<()> {
+fn check_config_contents(config: &Config, runner: &dyn CommandRunner) -> Result<()> {
     println!("\n{}", "Checking configuration contents:".bright_blue());
-    
-    // Check Nix packages content
-    let packages_path = if cfg!(target_os = "macos") {
-        config.get_expanded_path(&config.darwin_packages_path)?
-    } else {
-        config.get_expanded_path(&config.linux_packages_path)?
-    };
-
-    print!("Validating systemPackages array: ");
-    match fs::read_to_string(&packages_path) {
-        Ok(content) => {
-            match nix_editor::read::getarrvals(&content, "environment.systemPackages") {
-                Ok(packages) => {
-                    println!("{} ({} packages found)", "✓".green(), packages.len());
-                },
-                Err(e) => {
-                    println!("{} ({})", "⨯ Array not found or invalid".red(), e);
-                }
-            }
-        },
-        Err(e) => {
-            println!("{} ({})", "⨯ Failed to read file".red(), e);
-        }
-    }
-    
-    // Check Homebrew packages on macOS
-    if cfg!(target_os = "macos") {
-        let homebrew_path = config.get_expanded_path(&config.homebrew_packages_path)?;
-        
-        print!("Validating homebrew.brews array: ");
-        match fs::read_to_string(&homebrew_path) {
-            Ok(content) => {
-                match nix_editor::read::getarrvals(&content, "homebrew.brews") {
-                    Ok(packages) => {
-                        println!("{} ({} formulae found)", "✓".green(), packages.len());
-                    },
-                    Err(e) => {
-                        println!("{} ({})", "⨯ Array not found or invalid".red(), e);
-                    }
-                }
-            },
-            Err(e) => {
-                println!("{} ({})", "⨯ Failed to read file".red(), e);
-            }
-        }
 
-        print!("Validating homebrew.casks array: ");
-        match fs::read_to_string(&homebrew_path) {
-            Ok(content) => {
-                match nix_editor::read::getarrvals(&content, "homebrew.casks") {
-                    Ok(packages) => {
-                        println!("{} ({} casks found)", "✓".green(), packages.len());
-                    },
-                    Err(e) => {
-                        println!("{} ({})", "⨯ Array not found or invalid".red(), e);
-                    }
-                }
+    for backend in backend::registered(config, runner)? {
+        print!("Validating {} array: ", backend.list_file().attr());
+        match backend.validate() {
+            Ok(count) => {
+                println!("{} ({} {} packages found)", "✓".green(), count, backend.describe());
             },
             Err(e) => {
-                println!("{} ({})", "⨯ Failed to read file".red(), e);
+                println!("{} ({})", "⨯ Array not found or invalid".red(), e);
             }
         }
     }
@@ -152,14 +108,10 @@ fn check_config_contents(config: &Config) -> Result<()> {
     Ok(())
 }
 
-// Rest of the functions (check_commands, check_git_repo, test_nix_search, 
-// test_homebrew_search, check_search) remain unchanged
-
-fn check_commands() -> Result<()> {
+fn check_commands(runner: &dyn CommandRunner) -> Result<()> {
     println!("\n{}", "Checking required commands:".bright_blue());
     
     let commands = vec![
-        ("git", "Required for version control"),
         ("nix", "Required for package management"),
         ("brew", "Required for Homebrew package management (macOS only)"),
         ("make", "Required for running installation commands"),
@@ -167,12 +119,9 @@ fn check_commands() -> Result<()> {
     
     for (cmd, description) in commands {
         print!("{:<10} ({}): ", cmd, description);
-        match Command::new("which")
-            .arg(cmd)
-            .output() 
-        {
+        match runner.output(&Cmd::new("which").arg(cmd)) {
             Ok(output) => {
-                if output.status.success() {
+                if output.success() {
                     println!("{}", "✓".green());
                 } else {
                     println!("{}", "⨯ Not found".red());
@@ -190,92 +139,53 @@ fn check_commands() -> Result<()> {
 fn check_git_repo(config: &Config) -> Result<()> {
     println!("\n{}", "Checking git repository:".bright_blue());
     
-    let config_dir = config.get_expanded_path(&config.darwin_packages_path)?
-        .parent()
-        .ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))?
-        .to_path_buf();
-    
-    print!("Git repository status: ");
-    
-    let status = Command::new("git")
-        .args(&["status"])
-        .current_dir(&config_dir)
-        .output();
-        
-    match status {
-        Ok(output) => {
-            if output.status.success() {
-                println!("{}", "✓".green());
-                
-                // Check for uncommitted changes
-                print!("Checking for uncommitted changes: ");
-                let changes = Command::new("git")
-                    .args(&["status", "--porcelain"])
-                    .current_dir(&config_dir)
-                    .output()?;
-                    
-                if changes.stdout.is_empty() {
-                    println!("{}", "✓ Working directory clean".green());
-                } else {
-                    println!("{}", "! Uncommitted changes present".yellow());
-                }
-            } else {
-                println!("{}", "⨯ Not a git repository".red());
-            }
+    print!("Git repository: ");
+    let repo = match config.repo_root(&config.packages_path()?) {
+        Ok(repo) => {
+            println!("{}", repo.display());
+            repo
         },
-        Err(_) => {
-            println!("{}", "⨯ Failed to check git status".red());
+        Err(e) => {
+            println!("{} ({})", "⨯ Not found".red(), e);
+            return Ok(());
         }
+    };
+
+    print!("Checking for uncommitted changes: ");
+    match git::changed_files(&repo, &[]) {
+        Ok(changes) if changes.is_empty() => println!("{}", "✓ Working directory clean".green()),
+        Ok(_) => println!("{}", "! Uncommitted changes present".yellow()),
+        Err(e) => println!("{} ({})", "⨯ Failed to check git status".red(), e),
+    }
+
+    print!("Checking upstream: ");
+    let fetched = git::fetch(&repo);
+    match git::ahead_behind(&repo) {
+        Ok(None) => println!("{}", "! Current branch has no upstream".yellow()),
+        Ok(Some((_, 0))) => println!("{}", "✓ Up to date".green()),
+        Ok(Some((_, behind))) => {
+            let hint = if config.auto_pull { "pulled before the next change" } else { "enable auto_pull or pull before making changes" };
+            println!("{} ({})", format!("! Behind by {} commit(s)", behind).yellow(), hint);
+        },
+        Err(e) => println!("{} ({})", "⨯ Failed to compare with upstream".red(), e),
+    }
+    if let Err(e) = fetched {
+        println!("{} ({})", "! Could not fetch, the comparison may be outdated".yellow(), e);
     }
     
     Ok(())
 }
 
-// New function to test search without user interaction
-fn test_nix_search(query: &str) -> Result<bool> {
-    let output = Command::new("nix")
-        .args([
-            "--extra-experimental-features", "nix-command",
-            "--extra-experimental-features", "flakes",
-            "search", "nixpkgs", query,
-            "--json"
-        ])
-        .output()
-        .context("Failed to execute nix search")?;
-
-    Ok(output.status.success())
-}
-
-fn test_homebrew_search(query: &str) -> Result<bool> {
-    let output = Command::new("brew")
-        .args(["search", query])
-        .output()
-        .context("Failed to execute brew search")?;
-    Ok(output.status.success())
-}
-
-fn check_search(_config: &Config) -> Result<()> {
+fn check_search(config: &Config, runner: &dyn CommandRunner) -> Result<()> {
     println!("\n{}", "Testing search functionality:".bright_blue());
-    print!("Testing Nix search: ");
-    match test_nix_search("git") {
-        Ok(true) => {
-            println!("{}", "✓".green());
-        },
-        Ok(false) => {
-            println!("{}", "⨯ Search returned no results".red());
-        },
-        Err(e) => {
-            println!("{} ({})", "⨯ Search failed".red(), e);
-        }
-    }
 
-    if cfg!(target_os = "macos") {
-        print!("Testing Homebrew search: ");
-        match test_homebrew_search("git") {
-            Ok(true) => {
+    for backend in backend::registered(config, runner)? {
+        print!("Testing {} search: ", backend.describe());
+        match backend.search("git") {
+            Ok(packages) if !packages.is_empty() => {
                 println!("{}", "✓".green());
             },
-            Ok(false) => {
+            Ok(_) => {
                 println!("{}", "⨯ Search returned no results".red());
             },
             Err(e) => {
@@ -283,7 +193,6 @@ fn check_search(_config: &Config) -> Result<()> {
             }
         }
     }
-    
+
     Ok(())
 }
-
@@ -0,0 +1,33 @@
+use anyhow::Result;
+use colored::*;
+use std::path::Path;
+use yuki::{diff, Config};
+
+use super::transaction::Change;
+
+/// How running the hook `command` after changing `file` is shown in a plan.
+pub fn hook_plan(config: &Config, command: &str, file: &Path) -> Result<Option<String>> {
+    let command = command.trim();
+    if command.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(format!("{}  {}", command, format!("(in {})", config.work_dir(file)?.display()).dimmed())))
+}
+
+/// Print what a command would do instead of doing it: the diff of each
+/// changed file and the `commands` that would run, in order.
+pub fn print_plan(changes: &[Change], commands: &[String]) {
+    println!("🔍 Dry run, nothing was changed");
+    for change in changes {
+        println!();
+        println!("{}", diff::colored(&change.file, &change.old, &change.new));
+    }
+
+    if !commands.is_empty() {
+        println!();
+        println!("Would run:");
+        for command in commands {
+            println!("  {}", command);
+        }
+    }
+}
@@ -4,10 +4,19 @@ mod search;
 mod list;
 mod update;
 mod doctor;
+mod config;
+mod restore;
+mod commit;
+mod dry_run;
+mod transaction;
+#[cfg(test)]
+mod testing;
 
-pub use install::install_package;
-pub use uninstall::uninstall_package;
+pub use install::install_packages;
+pub use uninstall::uninstall_packages;
 pub use search::search_packages;
 pub use list::list_packages;
 pub use update::update_packages;
 pub use doctor::check_doctor;
+pub use config::{run_config, ConfigCommand};
+pub use restore::restore_backup;
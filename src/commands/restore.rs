@@ -0,0 +1,140 @@
+use anyhow::Result;
+use colored::*;
+use std::path::PathBuf;
+use yuki::backup::{self, Backup};
+use yuki::packages;
+use yuki::runner::CommandRunner;
+use yuki::Config;
+
+use super::dry_run::print_plan;
+use super::transaction::{lock, Change};
+
+/// The package files of the registered backends, without duplicates.
+fn package_files(config: &Config, runner: &dyn CommandRunner) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for list in packages::list(config, runner)? {
+        if !files.contains(&list.file) {
+            files.push(list.file);
+        }
+    }
+    Ok(files)
+}
+
+fn print_backups(file: &std::path::Path, backups: &[Backup]) {
+    println!("==> {}", file.display().to_string().bright_blue());
+    if backups.is_empty() {
+        println!("No backups");
+    }
+    for backup in backups {
+        match backup.time() {
+            Some(time) => println!("{}  {}", backup.name, time.format("%Y-%m-%d %H:%M:%S").to_string().dimmed()),
+            None => println!("{}", backup.name),
+        }
+    }
+}
+
+/// List the backups of the package files with `list`, otherwise restore the
+/// backup called `name`, or the newest one if no name is given. Every package
+/// file with a backup of that name is restored, as they were changed together.
+pub fn restore_backup(config: &Config, runner: &dyn CommandRunner, list: bool, name: Option<&str>) -> Result<()> {
+    let files = package_files(config, runner)?;
+
+    if list {
+        for (i, file) in files.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            print_backups(file, &backup::list(file)?);
+        }
+        return Ok(());
+    }
+
+    let _lock = lock(config)?;
+    let mut backups = Vec::new();
+    for file in &files {
+        backups.extend(backup::list(file)?);
+    }
+    let name = match name {
+        Some(name) => {
+            let name = name.trim_end_matches(".nix");
+            if !backups.iter().any(|b| b.name == name) {
+                return Err(anyhow::anyhow!("No backup named {}, see `yuki restore --list`", name));
+            }
+            name.to_string()
+        },
+        None => backups.iter()
+            .map(|b| b.name.clone())
+            .max()
+            .ok_or_else(|| anyhow::anyhow!("No backups of the package files yet"))?,
+    };
+    backups.retain(|b| b.name == name);
+
+    if config.dry_run {
+        let mut changes = Vec::new();
+        for backup in backups {
+            let old = std::fs::read_to_string(&backup.file).unwrap_or_default();
+            let new = std::fs::read_to_string(&backup.path)?;
+            changes.push(Change { file: backup.file, old, new });
+        }
+        print_plan(&changes, &[]);
+        return Ok(());
+    }
+
+    // The current contents are backed up together as well
+    let undo_name = backup::new_name();
+    let mut restored = false;
+    for backup in &backups {
+        if backup::restore(backup, &undo_name)? {
+            println!("⏪ Restored {} from backup {}", backup.file.display(), backup.name.bright_blue());
+            restored = true;
+        } else {
+            println!("✓ {} already matches backup {}, nothing changed", backup.file.display(), backup.name.bright_blue());
+        }
+    }
+    if restored {
+        println!("The restored files are not committed, review them with git diff");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use yuki::runner::FakeRunner;
+    use yuki::Platform;
+
+    use crate::commands::testing;
+
+    #[test]
+    fn restores_every_file_of_a_change() {
+        let (_dir, repo) = testing::repo(&[
+            ("apps.nix", "{ pkgs, ... }:\n{\n  environment.systemPackages = with pkgs; [\n    vim\n  ];\n}\n"),
+            ("brew.nix", "{ ... }:\n{\n  homebrew.brews = [\n    \"wget\"\n  ];\n}\n"),
+        ]);
+        let runner = FakeRunner::new();
+        let config = Config {
+            platform: Platform::Darwin,
+            darwin_packages_path: repo.join("apps.nix").to_string_lossy().into_owned(),
+            homebrew_packages_path: repo.join("brew.nix").to_string_lossy().into_owned(),
+            auto_commit: false,
+            ..testing::config(&repo.join("apps.nix"))
+        };
+        let original: Vec<String> = ["apps.nix", "brew.nix"].iter()
+            .map(|file| fs::read_to_string(repo.join(file)).unwrap())
+            .collect();
+
+        let name = backup::new_name();
+        for (file, content) in ["apps.nix", "brew.nix"].iter().zip(&original) {
+            backup::replace(&repo.join(file), &name, content, "{ }\n").unwrap();
+        }
+        restore_backup(&config, &runner, false, None).unwrap();
+
+        for (file, content) in ["apps.nix", "brew.nix"].iter().zip(&original) {
+            assert_eq!(&fs::read_to_string(repo.join(file)).unwrap(), content);
+        }
+        // Restoring again finds both files unchanged
+        restore_backup(&config, &runner, false, Some(&name)).unwrap();
+        assert_eq!(backup::list(&repo.join("apps.nix")).unwrap().len(), 2);
+    }
+}
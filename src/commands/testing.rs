@@ -0,0 +1,78 @@
+//! Helpers for the command tests: throwaway dotfiles repositories and a
+//! state directory that keeps locks and backups out of the user's.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+use yuki::git;
+use yuki::runner::FakeRunner;
+use yuki::{Config, Platform};
+
+/// Point the state directory at a temporary one for the whole test run.
+/// This runs before `main`, while the process has a single thread, as
+/// changing the environment races with threads reading it.
+extern "C" fn isolate_state() {
+    let dir = std::env::temp_dir().join(format!("yuki-test-{}", std::process::id()));
+    std::env::set_var("YUKI_STATE_DIR", dir);
+}
+
+#[used]
+#[cfg_attr(target_vendor = "apple", link_section = "__DATA,__mod_init_func")]
+#[cfg_attr(not(target_vendor = "apple"), link_section = ".init_array")]
+static ISOLATE_STATE: extern "C" fn() = isolate_state;
+
+/// A git repository with an identity, holding `files` (path and content) in
+/// its first commit.
+pub fn repo(files: &[(&str, &str)]) -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("dotfiles");
+
+    let repo = git2::Repository::init_opts(&root, git2::RepositoryInitOptions::new().initial_head("main")).unwrap();
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "yuki").unwrap();
+    config.set_str("user.email", "yuki@example.com").unwrap();
+    config.set_bool("commit.gpgsign", false).unwrap();
+
+    let paths: Vec<PathBuf> = files.iter()
+        .map(|(name, content)| {
+            let path = root.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+            path
+        })
+        .collect();
+    git::commit_files(&FakeRunner::new(), &root, &paths, "init").unwrap();
+    (dir, root)
+}
+
+/// Config managing the Linux package file `file`, with the default hooks.
+pub fn config(file: &Path) -> Config {
+    Config {
+        platform: Platform::Linux,
+        linux_packages_path: file.to_string_lossy().into_owned(),
+        lock_timeout: 10,
+        ..Config::default()
+    }
+}
+
+/// Summaries of the commits on the current branch, newest first.
+pub fn log(repo: &Path) -> Vec<String> {
+    let repo = git2::Repository::open(repo).unwrap();
+    let mut walk = repo.revwalk().unwrap();
+    walk.push_head().unwrap();
+    walk.map(|oid| repo.find_commit(oid.unwrap()).unwrap().summary().unwrap().to_string())
+        .collect()
+}
+
+/// Paths changed by the newest commit.
+pub fn committed_files(repo: &Path) -> Vec<String> {
+    let repo = git2::Repository::open(repo).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    let parent = head.parent(0).unwrap();
+    let diff = repo.diff_tree_to_tree(Some(&parent.tree().unwrap()), Some(&head.tree().unwrap()), None).unwrap();
+    diff.deltas()
+        .filter_map(|delta| delta.new_file().path().map(|path| path.display().to_string()))
+        .collect()
+}
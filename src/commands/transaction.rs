@@ -0,0 +1,477 @@
+use anyhow::{Context, Result};
+use colored::*;
+use std::path::{Path, PathBuf};
+use yuki::{backup, git, packages};
+use yuki::lock::Lock;
+use yuki::runner::{Cmd, CommandRunner};
+use yuki::{Config, Edit, Error};
+
+use super::commit::{commit_changes, commit_plan, edit_context, pull_changes, pull_plan};
+use super::dry_run::{hook_plan, print_plan};
+
+/// What a transaction does to each of its packages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Install,
+    Uninstall,
+}
+
+impl Action {
+    fn name(self) -> &'static str {
+        match self {
+            Action::Install => "install",
+            Action::Uninstall => "uninstall",
+        }
+    }
+
+    fn command(self, config: &Config) -> &str {
+        match self {
+            Action::Install => &config.install_command,
+            Action::Uninstall => &config.uninstall_command,
+        }
+    }
+
+    fn message(self, config: &Config) -> &str {
+        match self {
+            Action::Install => &config.install_message,
+            Action::Uninstall => &config.uninstall_message,
+        }
+    }
+
+    /// The error for a package that is already in the requested state.
+    fn unchanged(self, package: &str) -> Error {
+        match self {
+            Action::Install => Error::AlreadyPresent(package.to_string()),
+            Action::Uninstall => Error::NotFound(package.to_string()),
+        }
+    }
+}
+
+/// A package to install or uninstall.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub package: String,
+    /// Id of the backend whose package file to edit
+    pub source: &'static str,
+    /// Version for the commit message, empty if unknown
+    pub version: String,
+}
+
+/// The contents of a package file before and after a transaction.
+pub struct Change {
+    pub file: PathBuf,
+    pub old: String,
+    pub new: String,
+}
+
+/// Take the lock on package changes, unless this is a dry run.
+pub fn lock(config: &Config) -> Result<Option<Lock>> {
+    if config.dry_run {
+        return Ok(None);
+    }
+    let lock = Lock::acquire(config.lock_timeout(), |pid| {
+        println!("⏳ Waiting for another yuki process (pid {})...", pid);
+    })?;
+    Ok(Some(lock))
+}
+
+/// Write the original contents of `changes` back.
+fn roll_back(changes: &[Change]) -> Result<()> {
+    for change in changes {
+        backup::write_atomic(&change.file, &change.old)
+            .with_context(|| format!("Failed to restore {}", change.file.display()))?;
+    }
+    Ok(())
+}
+
+fn file_list(changes: &[Change]) -> String {
+    changes.iter()
+        .map(|change| change.file.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Apply `action` to every requested package as one transaction: all
+/// package files are edited, the hook command runs once and a single commit
+/// per repository records every package. Packages already in the requested
+/// state are skipped. Nothing is written unless every edit succeeds.
+pub fn apply(config: &Config, runner: &dyn CommandRunner, action: Action, requests: &[Request]) -> Result<()> {
+    let _lock = lock(config)?;
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for request in requests {
+        let file = packages::file_of(config, runner, request.source)?;
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+
+    // Refuse to touch files with pending changes that would end up in our
+    // commit, and tell a dry run that the change would be refused
+    if config.auto_commit {
+        for file in &files {
+            git::ensure_clean(&config.repo_root(file)?, std::slice::from_ref(file))?;
+        }
+    }
+    if !config.dry_run {
+        let mut pulled: Vec<PathBuf> = Vec::new();
+        for file in &files {
+            let repo = config.work_dir(file)?;
+            if !pulled.contains(&repo) {
+                pull_changes(config, file)?;
+                pulled.push(repo);
+            }
+        }
+    }
+
+    if action == Action::Install && config.verify_packages {
+        for request in requests {
+            packages::verify(config, runner, request.source, &request.package)?;
+        }
+    }
+
+    // Edit the contents in memory first, so a failing edit leaves every file alone
+    let mut changes = Vec::new();
+    for request in requests {
+        let content = packages::contents_of(config, runner, request.source)?;
+        let file = packages::file_of(config, runner, request.source)?;
+        if !changes.iter().any(|change: &Change| change.file == file) {
+            changes.push(Change { file, old: content.clone(), new: content });
+        }
+    }
+
+    let mut edits: Vec<Edit> = Vec::new();
+    for request in requests {
+        let file = packages::file_of(config, runner, request.source)?;
+        let Some(change) = changes.iter_mut().find(|change| change.file == file) else {
+            continue;
+        };
+        let (edit, new) = match action {
+            Action::Install => packages::preview_add(config, runner, request.source, &change.new, &request.package)?,
+            Action::Uninstall => packages::preview_remove(config, runner, request.source, &change.new, &request.package)?,
+        };
+        if !edit.changed {
+            if requests.len() == 1 {
+                return Err(action.unchanged(&request.package).into());
+            }
+            println!("⚠️  Skipping {}: {}", request.package, action.unchanged(&request.package));
+            continue;
+        }
+        change.new = new;
+        edits.push(edit);
+    }
+    if edits.is_empty() {
+        return Err(action.unchanged(&requests[0].package).into());
+    }
+    changes.retain(|change| change.old != change.new);
+
+    // Only a single package has a meaningful <version>
+    let version = match requests {
+        [request] => request.version.as_str(),
+        _ => "",
+    };
+
+    if config.dry_run {
+        let mut commands: Vec<String> = pull_plan(config).into_iter().collect();
+        commands.extend(hook_plan(config, action.command(config), &changes[0].file)?);
+        if config.auto_commit {
+            for (repo, edits) in by_repo(config, &edits)? {
+                let files = edited_files(&edits);
+                let context = edit_context(config, &repo, &edits, version);
+                commands.extend(commit_plan(config, &repo, &files, &context, action.message(config))?);
+            }
+        }
+        print_plan(&changes, &commands);
+        return Ok(());
+    }
+
+    // The backups share a name, so `yuki restore` undoes the whole change
+    let backup_name = backup::new_name();
+    for (i, change) in changes.iter().enumerate() {
+        if let Err(e) = backup::replace(&change.file, &backup_name, &change.old, &change.new) {
+            roll_back(&changes[..i])?;
+            return Err(e);
+        }
+        println!("📄 Using package file: {}", change.file.display());
+    }
+    let names: Vec<String> = edits.iter().map(|edit| edit.package.clone()).collect();
+    match action {
+        Action::Install => println!("✨ Successfully added {}", names.join(", ").green()),
+        Action::Uninstall => println!("✨ Successfully removed {}", names.join(", ").green()),
+    }
+
+    finish(config, runner, &changes, action.name(), action.command(config), || {
+        // Handle git operations if auto_commit is enabled
+        if config.auto_commit {
+            for (repo, edits) in by_repo(config, &edits)? {
+                let files = edited_files(&edits);
+                let context = edit_context(config, &repo, &edits, version);
+                commit_changes(config, runner, &repo, &files, &context, action.message(config))?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// `edits` grouped by the repository their file is committed to.
+fn by_repo(config: &Config, edits: &[Edit]) -> Result<Vec<(PathBuf, Vec<Edit>)>> {
+    let mut groups: Vec<(PathBuf, Vec<Edit>)> = Vec::new();
+    for edit in edits {
+        let repo = config.repo_root(&edit.file)?;
+        match groups.iter_mut().find(|(root, _)| *root == repo) {
+            Some((_, group)) => group.push(edit.clone()),
+            None => groups.push((repo, vec![edit.clone()])),
+        }
+    }
+    Ok(groups)
+}
+
+/// The distinct files of `edits`, in order.
+fn edited_files(edits: &[Edit]) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for edit in edits {
+        if !files.contains(&edit.file) {
+            files.push(edit.file.clone());
+        }
+    }
+    files
+}
+
+/// Run the `name` hook `command` in `work_dir`, streaming its output.
+fn run_hook(runner: &dyn CommandRunner, name: &str, command: &str, work_dir: &Path) -> Result<()> {
+    let command = command.trim();
+    if command.is_empty() {
+        return Ok(());
+    }
+    println!("🔄 Running {} command: {}", name, command.bright_blue());
+
+    let output = runner.stream(&Cmd::shell(command).current_dir(work_dir))
+        .with_context(|| Error::HookFailed {
+            command: command.to_string(),
+            status: -1,
+        })?;
+
+    if !output.success() {
+        return Err(Error::HookFailed {
+            command: command.to_string(),
+            status: output.status,
+        }.into());
+    }
+    println!("✅ {} command completed successfully", name[..1].to_uppercase() + &name[1..]);
+    Ok(())
+}
+
+/// Finish the edits in `changes`: run the `name` hook `command` against the
+/// edited files first, and `commit` only once it succeeded. If the hook
+/// fails, the files are restored and nothing is committed, unless
+/// `keep_on_failure` is set.
+fn finish(config: &Config, runner: &dyn CommandRunner, changes: &[Change], name: &str, command: &str, commit: impl FnOnce() -> Result<()>) -> Result<()> {
+    let Err(e) = run_hook(runner, name, command, &config.work_dir(&changes[0].file)?) else {
+        return commit();
+    };
+
+    if config.keep_on_failure {
+        println!("⚠️  Keeping the change although the {} command failed", name);
+        commit()?;
+        return Err(e);
+    }
+
+    roll_back(changes).context("Failed to roll back the change")?;
+    println!("↩️  Rolled back {}, nothing was committed", file_list(changes));
+    Err(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use yuki::runner::{FakeRunner, Output};
+    use yuki::Platform;
+
+    use crate::commands::testing::{self, committed_files, log};
+
+    const APPS: &str = "{ pkgs, ... }:\n{\n  environment.systemPackages = with pkgs; [\n    git\n    vim\n  ];\n}\n";
+    const EVAL: &str = "nix --extra-experimental-features nix-command --extra-experimental-features flakes eval";
+
+    fn requests(packages: &[&str]) -> Vec<Request> {
+        packages.iter()
+            .map(|package| Request {
+                package: package.to_string(),
+                source: "nixpkgs",
+                version: String::new(),
+            })
+            .collect()
+    }
+
+    fn hooks(runner: &FakeRunner) -> Vec<String> {
+        runner.command_lines().into_iter().filter(|line| line.starts_with("sh -c")).collect()
+    }
+
+    #[test]
+    fn installs_batch_in_one_commit() {
+        let (_dir, repo) = testing::repo(&[("apps.nix", APPS)]);
+        let runner = FakeRunner::new();
+
+        apply(&testing::config(&repo.join("apps.nix")), &runner, Action::Install, &requests(&["ripgrep", "git", "fd"])).unwrap();
+
+        let content = fs::read_to_string(repo.join("apps.nix")).unwrap();
+        assert!(content.contains("    ripgrep\n") && content.contains("    fd\n"));
+        assert_eq!(log(&repo), ["installed ripgrep, fd", "init"]);
+        assert_eq!(hooks(&runner), ["sh -c make"]);
+    }
+
+    #[test]
+    fn uninstalls_package() {
+        let (_dir, repo) = testing::repo(&[("apps.nix", APPS)]);
+        let runner = FakeRunner::new();
+        let config = Config {
+            uninstall_message: "removed <package> from <file>".to_string(),
+            ..testing::config(&repo.join("apps.nix"))
+        };
+
+        apply(&config, &runner, Action::Uninstall, &requests(&["vim"])).unwrap();
+
+        assert!(!fs::read_to_string(repo.join("apps.nix")).unwrap().contains("vim"));
+        assert_eq!(log(&repo), ["removed vim from apps.nix", "init"]);
+        assert_eq!(committed_files(&repo), ["apps.nix"]);
+        // Uninstalling doesn't check that the package exists
+        assert_eq!(runner.command_lines(), ["sh -c make"]);
+    }
+
+    #[test]
+    fn single_package_in_requested_state_fails() {
+        let (_dir, repo) = testing::repo(&[("apps.nix", APPS)]);
+        let runner = FakeRunner::new();
+        let config = testing::config(&repo.join("apps.nix"));
+
+        let e = apply(&config, &runner, Action::Install, &requests(&["git"])).unwrap_err();
+        assert_eq!(Error::exit_code_of(&e), 6);
+        let e = apply(&config, &runner, Action::Uninstall, &requests(&["emacs"])).unwrap_err();
+        assert_eq!(Error::exit_code_of(&e), 7);
+
+        assert_eq!(log(&repo), ["init"]);
+        assert!(hooks(&runner).is_empty());
+    }
+
+    #[test]
+    fn failed_hook_rolls_back_every_file() {
+        let (_dir, repo) = testing::repo(&[("apps.nix", APPS)]);
+        let runner = FakeRunner::new();
+        runner.on("sh -c", Output::err(2, "build failed"));
+
+        let e = apply(&testing::config(&repo.join("apps.nix")), &runner, Action::Uninstall, &requests(&["git", "vim"])).unwrap_err();
+
+        assert_eq!(Error::exit_code_of(&e), 9);
+        assert_eq!(fs::read_to_string(repo.join("apps.nix")).unwrap(), APPS);
+        assert_eq!(log(&repo), ["init"]);
+    }
+
+    #[test]
+    fn keep_on_failure_commits_and_fails() {
+        let (_dir, repo) = testing::repo(&[("apps.nix", APPS)]);
+        let runner = FakeRunner::new();
+        runner.on("sh -c", Output::err(2, "build failed"));
+        let config = Config {
+            keep_on_failure: true,
+            ..testing::config(&repo.join("apps.nix"))
+        };
+
+        let e = apply(&config, &runner, Action::Uninstall, &requests(&["vim"])).unwrap_err();
+
+        assert_eq!(Error::exit_code_of(&e), 9);
+        assert_eq!(log(&repo), ["removed vim", "init"]);
+    }
+
+    #[test]
+    fn unknown_package_changes_nothing() {
+        let (_dir, repo) = testing::repo(&[("apps.nix", APPS)]);
+        let runner = FakeRunner::new();
+        runner.on(EVAL, Output::err(1, "error: flake 'flake:nixpkgs' does not provide attribute 'ripgrp'"));
+
+        let e = apply(&testing::config(&repo.join("apps.nix")), &runner, Action::Install, &requests(&["fd", "ripgrp"])).unwrap_err();
+
+        assert_eq!(Error::exit_code_of(&e), 11);
+        assert_eq!(fs::read_to_string(repo.join("apps.nix")).unwrap(), APPS);
+        assert!(hooks(&runner).is_empty());
+    }
+
+    #[test]
+    fn refuses_file_with_uncommitted_changes() {
+        let (_dir, repo) = testing::repo(&[("apps.nix", APPS)]);
+        let runner = FakeRunner::new();
+        let edited = APPS.replace("vim", "emacs");
+        fs::write(repo.join("apps.nix"), &edited).unwrap();
+
+        let e = apply(&testing::config(&repo.join("apps.nix")), &runner, Action::Install, &requests(&["fd"])).unwrap_err();
+
+        assert_eq!(Error::exit_code_of(&e), 10);
+        assert_eq!(fs::read_to_string(repo.join("apps.nix")).unwrap(), edited);
+    }
+
+    #[test]
+    fn dry_run_changes_nothing() {
+        let (_dir, repo) = testing::repo(&[("apps.nix", APPS)]);
+        let runner = FakeRunner::new();
+        let config = Config {
+            dry_run: true,
+            verify_packages: false,
+            ..testing::config(&repo.join("apps.nix"))
+        };
+
+        apply(&config, &runner, Action::Install, &requests(&["ripgrep"])).unwrap();
+
+        assert_eq!(fs::read_to_string(repo.join("apps.nix")).unwrap(), APPS);
+        assert_eq!(log(&repo), ["init"]);
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    fn dry_run_reports_uncommitted_changes() {
+        let (_dir, repo) = testing::repo(&[("apps.nix", APPS)]);
+        let runner = FakeRunner::new();
+        fs::write(repo.join("apps.nix"), APPS.replace("vim", "emacs")).unwrap();
+        let config = Config {
+            dry_run: true,
+            verify_packages: false,
+            ..testing::config(&repo.join("apps.nix"))
+        };
+
+        let e = apply(&config, &runner, Action::Install, &requests(&["fd"])).unwrap_err();
+
+        assert_eq!(Error::exit_code_of(&e), 10);
+        let config = Config { auto_commit: false, ..config };
+        apply(&config, &runner, Action::Install, &requests(&["fd"])).unwrap();
+    }
+
+    #[test]
+    fn edits_darwin_files_from_any_platform() {
+        let (_dir, repo) = testing::repo(&[
+            ("apps.nix", APPS),
+            ("brew.nix", "{ ... }:\n{\n  homebrew.brews = [\n    \"wget\"\n  ];\n}\n"),
+        ]);
+        let runner = FakeRunner::new();
+        let config = Config {
+            platform: Platform::Darwin,
+            darwin_packages_path: repo.join("apps.nix").to_string_lossy().into_owned(),
+            homebrew_packages_path: repo.join("brew.nix").to_string_lossy().into_owned(),
+            uninstall_message: "removed <packages> from <source>".to_string(),
+            ..testing::config(&repo.join("apps.nix"))
+        };
+        let mut requests = requests(&["vim"]);
+        requests.push(Request {
+            package: "wget".to_string(),
+            source: "brew",
+            version: String::new(),
+        });
+
+        apply(&config, &runner, Action::Uninstall, &requests).unwrap();
+
+        assert_eq!(log(&repo), ["removed vim, wget from nixpkgs, brew", "init"]);
+        assert_eq!(committed_files(&repo), ["apps.nix", "brew.nix"]);
+        assert_eq!(hooks(&runner), ["sh -c make"]);
+        // Both files were backed up as one change
+        let apps = backup::list(&repo.join("apps.nix")).unwrap();
+        let brew = backup::list(&repo.join("brew.nix")).unwrap();
+        assert_eq!(apps[0].name, brew[0].name);
+    }
+}
@@ -1,309 +1,76 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use colored::*;
-use nix_editor::{write, read};
-use std::fs;
 use skim::{
     prelude::*,
     Skim,
 };
-use crate::config::Config;
-use std::process::{Command, Stdio};
-use std::io::{BufRead, BufReader};
-
-#[derive(Debug)]
-struct UninstallOption {
-    package: String,
-    location: PackageLocation,
-}
-
-#[derive(Debug)]
-enum PackageLocation {
-    Nix,
-    HomebrewFormula,
-    HomebrewCask,
-}
-
-impl UninstallOption {
-    fn to_string(&self) -> String {
-        match self.location {
-            PackageLocation::Nix => format!("{} (nixpkgs)", self.package),
-            PackageLocation::HomebrewFormula => format!("{} (homebrew formula)", self.package),
-            PackageLocation::HomebrewCask => format!("{} (homebrew cask)", self.package),
-        }
-    }
-}
-
-pub fn uninstall_package(config: &Config, package: &str) -> Result<()> {
-    let mut uninstall_options = Vec::new();
-
-    // Check Nix packages
-    let nix_path = if cfg!(target_os = "macos") {
-        config.get_expanded_path(&config.darwin_packages_path)?
-    } else {
-        config.get_expanded_path(&config.linux_packages_path)?
-    };
-
-    if nix_path.exists() {
-        let content = fs::read_to_string(&nix_path)?;
-        if let Ok(packages) = read::getarrvals(&content, "environment.systemPackages") {
-            if packages.iter().any(|p| p.contains(package)) {
-                uninstall_options.push(UninstallOption {
-                    package: package.to_string(),
-                    location: PackageLocation::Nix,
-                });
-            }
-        }
-    }
-
-    // Check Homebrew packages on macOS
-    if cfg!(target_os = "macos") {
-        let homebrew_path = config.get_expanded_path(&config.homebrew_packages_path)?;
-        if homebrew_path.exists() {
-            let content = fs::read_to_string(&homebrew_path)?;
-            
-            // Check formulae
-            if let Ok(packages) = read::getarrvals(&content, "homebrew.brews") {
-                if packages.iter().any(|p| p.contains(package)) {
-                    uninstall_options.push(UninstallOption {
-                        package: package.to_string(),
-                        location: PackageLocation::HomebrewFormula,
-                    });
-                }
-            }
-            
-            // Check casks
-            if let Ok(packages) = read::getarrvals(&content, "homebrew.casks") {
-                if packages.iter().any(|p| p.contains(package)) {
-                    uninstall_options.push(UninstallOption {
-                        package: package.to_string(),
-                        location: PackageLocation::HomebrewCask,
-                    });
-                }
+use yuki::packages;
+use yuki::runner::CommandRunner;
+use yuki::{Config, Error};
+
+use super::transaction::{apply, Action, Request};
+
+/// Uninstall each of `packages`, with a single commit and hook run for all
+/// of them. Packages that aren't installed are skipped, unless none of them
+/// is.
+pub fn uninstall_packages(config: &Config, runner: &dyn CommandRunner, packages: &[String]) -> Result<()> {
+    let mut requests = Vec::new();
+    for package in packages {
+        // Collect every source that has the package
+        let uninstall_options = packages::sources_of(config, runner, package)?;
+        if uninstall_options.is_empty() {
+            if packages.len() == 1 {
+                return Err(Error::NotFound(package.to_string()).into());
             }
+            println!("⚠️  Skipping {}: {}", package, Error::NotFound(package.to_string()));
+            continue;
         }
-    }
 
-    if uninstall_options.is_empty() {
-        println!("⚠️  Package {} is not installed!", package.yellow());
-        return Ok(());
+        let Some(source) = choose_source(package, &uninstall_options) else {
+            return Ok(());
+        };
+        println!("🗑️  Uninstalling {} package: {}", source, package.bright_blue());
+        requests.push(Request {
+            package: package.clone(),
+            source,
+            version: String::new(),
+        });
     }
 
-    // If there's more than one option, let the user choose
-    let selected_option = if uninstall_options.len() > 1 {
-        let items: Vec<String> = uninstall_options.iter()
-            .map(|opt| opt.to_string())
-            .collect();
-
-        let options = SkimOptionsBuilder::default()
-            .height(Some("50%"))
-            .multi(false)
-            .prompt(Some("Select package to uninstall > "))
-            .build()
-            .unwrap();
-
-        let item_reader = SkimItemReader::default();
-        let items = item_reader.of_bufread(std::io::Cursor::new(items.join("\n")));
-
-        match Skim::run_with(&options, Some(items)) {
-            Some(output) => {
-                if output.is_abort {
-                    return Ok(());
-                }
-                if let Some(selected) = output.selected_items.first() {
-                    let idx = uninstall_options.iter()
-                        .position(|opt| opt.to_string() == selected.output())
-                        .unwrap_or(0);
-                    &uninstall_options[idx]
-                } else {
-                    return Ok(());
-                }
-            },
-            None => return Ok(()),
-        }
-    } else {
-        &uninstall_options[0]
-    };
-
-    match selected_option.location {
-        PackageLocation::Nix => uninstall_nix_package(config, package),
-        PackageLocation::HomebrewFormula => uninstall_homebrew_package(config, package, false),
-        PackageLocation::HomebrewCask => uninstall_homebrew_package(config, package, true),
+    if requests.is_empty() {
+        return Err(Error::NotFound(packages[0].to_string()).into());
     }
+    apply(config, runner, Action::Uninstall, &requests)
 }
 
-fn uninstall_nix_package(config: &Config, package: &str) -> Result<()> {
-    let packages_path = if cfg!(target_os = "macos") {
-        config.get_expanded_path(&config.darwin_packages_path)?
-    } else {
-        config.get_expanded_path(&config.linux_packages_path)?
-    };
-
-    println!("🗑️  Uninstalling Nix package: {}", package.bright_blue());
-    println!("📄 Using configuration file: {}", packages_path.display());
-
-    let file_content = fs::read_to_string(&packages_path)
-        .context("Failed to read configuration file")?;
-
-    match write::rmarr(&file_content, "environment.systemPackages", vec![package.to_string()]) {
-        Ok(new_content) => {
-            fs::write(&packages_path, new_content)
-                .context("Failed to write configuration file")?;
-            
-            handle_post_uninstall(config, package)?;
-        },
-        Err(e) => {
-            println!("❌ Failed to remove {}", package.red());
-            println!("Error: {}", e);
-        }
+/// The source to remove `package` from. If there's more than one option the
+/// user chooses; `None` if they cancelled.
+fn choose_source(package: &str, uninstall_options: &[&'static str]) -> Option<&'static str> {
+    if uninstall_options.len() == 1 {
+        return Some(uninstall_options[0]);
     }
 
-    Ok(())
-}
-
-fn uninstall_homebrew_package(config: &Config, package: &str, is_cask: bool) -> Result<()> {
-    let packages_path = config.get_expanded_path(&config.homebrew_packages_path)?;
-    println!("🗑️  Uninstalling Homebrew {}: {}", 
-        if is_cask { "cask" } else { "formula" },
-        package.bright_blue()
-    );
-    println!("📄 Using configuration file: {}", packages_path.display());
+    let items: Vec<String> = uninstall_options.iter()
+        .map(|source| format!("{} ({})", package, source))
+        .collect();
 
-    let file_content = fs::read_to_string(&packages_path)
-        .context("Failed to read configuration file")?;
+    let options = SkimOptionsBuilder::default()
+        .height(String::from("50%"))
+        .multi(false)
+        .prompt(String::from("Select package to uninstall > "))
+        .build()
+        .unwrap();
 
-    let array_path = if is_cask { "homebrew.casks" } else { "homebrew.brews" };
+    let item_reader = SkimItemReader::default();
+    let skim_items = item_reader.of_bufread(std::io::Cursor::new(items.join("\n")));
 
-    // First verify the package exists
-    match read::getarrvals(&file_content, array_path) {
-        Ok(packages) => {
-            // Check if package exists (case-sensitive)
-            let package_exists = packages.iter().any(|p| {
-                let cleaned = p.trim()
-                    .trim_matches('"')
-                    .trim_end_matches(';')
-                    .trim();
-                cleaned == package
-            });
-
-            if !package_exists {
-                println!("⚠️  Package {} is not installed!", package.yellow());
-                return Ok(());
-            }
-
-            // Create the package string as it appears in the file
-            let package_str = if package.contains('@') {
-                package.to_string() // Don't quote strings with @ symbol
-            } else {
-                format!("\"{}\"", package) // Quote normal package names
-            };
-
-            match write::rmarr(&file_content, array_path, vec![package_str]) {
-                Ok(new_content) => {
-                    fs::write(&packages_path, new_content)
-                        .context("Failed to write configuration file")?;
-                    
-                    handle_post_uninstall(config, package)?;
-                },
-                Err(e) => {
-                    println!("❌ Failed to remove {}", package.red());
-                    println!("Error: {}", e);
-                }
-            }
-        },
-        Err(read::ReadError::NoAttr) => {
-            println!("⚠️  No packages found in configuration");
-        },
-        Err(e) => {
-            println!("❌ Failed to read packages: {}", e);
-        }
+    let output = Skim::run_with(&options, Some(skim_items))?;
+    if output.is_abort {
+        return None;
     }
-
-    Ok(())
-}
-
-
-fn handle_post_uninstall(config: &Config, package: &str) -> Result<()> {
-    println!("✨ Successfully removed {}", package.green());
-    
-    // Get home directory
-    let home_dir = dirs::home_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
-
-    // Handle git operations if auto_commit is enabled
-    if config.auto_commit {
-        let commit_msg = config.uninstall_message.replace("<package>", package);
-        
-        if let Ok(_) = Command::new("git")
-            .args(&["add", "."])
-            .current_dir(&home_dir.join("dotfiles"))
-            .output() {
-            if let Ok(_) = Command::new("git")
-                .args(&["commit", "-m", &commit_msg])
-                .current_dir(&home_dir.join("dotfiles"))
-                .output() {
-                println!("📝 Changes committed to git");
-
-                if config.auto_push {
-                    if let Ok(_) = Command::new("git")
-                        .args(&["push"])
-                        .current_dir(&home_dir.join("dotfiles"))
-                        .output() {
-                        println!("🚀 Changes pushed to remote");
-                    }
-                }
-            }
-        }
-    }
-
-    // Run uninstall command
-    if !config.uninstall_command.is_empty() {
-        let clean_command = config.uninstall_command.trim();
-        println!("🔄 Running uninstall command: {}", clean_command.bright_blue());
-        
-        // Create command with piped output
-        let mut child = Command::new("sh")
-            .args(&["-c", clean_command])
-            .current_dir(&home_dir.join("dotfiles"))
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .context("Failed to spawn command")?;
-
-        // Handle stdout in real-time
-        if let Some(stdout) = child.stdout.take() {
-            let stdout_reader = BufReader::new(stdout);
-            for line in stdout_reader.lines() {
-                if let Ok(line) = line {
-                    println!("{}", line);
-                }
-            }
-        }
-
-        // Handle stderr in real-time
-        if let Some(stderr) = child.stderr.take() {
-            let stderr_reader = BufReader::new(stderr);
-            for line in stderr_reader.lines() {
-                if let Ok(line) = line {
-                    eprintln!("{}", line.red());
-                }
-            }
-        }
-
-        // Wait for the command to complete and check status
-        match child.wait() {
-            Ok(status) => {
-                if status.success() {
-                    println!("✅ Uninstall command completed successfully");
-                } else {
-                    return Err(anyhow::anyhow!("Uninstall command failed with status: {}", status));
-                }
-            },
-            Err(e) => {
-                println!("❌ Failed to execute uninstall command: {}", e.to_string().red());
-                return Err(anyhow::anyhow!("Failed to execute uninstall command: {}", e));
-            }
-        }
-    }
-
-    Ok(())
+    let selected = output.selected_items.first()?;
+    let idx = items.iter()
+        .position(|item| *item == selected.output())
+        .unwrap_or(0);
+    Some(uninstall_options[idx])
 }
@@ -1,89 +1,213 @@
 use anyhow::{Context, Result};
 use colored::*;
-use std::process::Command;
-use crate::config::Config;
+use std::path::{Path, PathBuf};
+use yuki::runner::{Cmd, CommandRunner};
+use yuki::{flake, git, Config, Error, MessageContext};
 
-pub fn update_packages(config: &Config) -> Result<()> {
+use super::commit::{commit_changes, commit_plan, pull_changes, pull_plan};
+use super::dry_run::{hook_plan, print_plan};
+use super::transaction::lock;
+
+/// The lock file of the flake `packages_path` belongs to, or of the
+/// directory the update command runs in if there is no flake.
+fn flake_lock(config: &Config, packages_path: &Path) -> Result<PathBuf> {
+    let dir = match flake::find_dir(packages_path) {
+        Some(dir) => dir,
+        None => config.work_dir(packages_path)?,
+    };
+    Ok(dir.join("flake.lock"))
+}
+
+pub fn update_packages(config: &Config, runner: &dyn CommandRunner) -> Result<()> {
     println!("🔄 Updating packages...");
+    if config.dry_run {
+        return preview_update(config);
+    }
+    let _lock = lock(config)?;
+
+    let packages_path = config.packages_path()?;
+    let lock_file = flake_lock(config, &packages_path)?;
+
+    // Files that were already modified or untracked are the user's work in
+    // progress, only what the update changes gets committed
+    let dirty = if config.auto_commit {
+        let repo = config.repo_root(&packages_path)?;
+        git::ensure_clean(&repo, &[packages_path.clone(), lock_file.clone()])?;
+        git::changed_files(&repo, &[])?
+    } else {
+        Vec::new()
+    };
 
-    // Get the base directory for running commands
-    let config_dir = config.get_expanded_path(&config.darwin_packages_path)?
-        .parent()
-        .ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))?
-        .to_path_buf();
+    pull_changes(config, &packages_path)?;
+    let old_revisions = flake::locked_revisions(&lock_file).unwrap_or_default();
 
     // If there's a custom update command, run it
     if !config.update_command.is_empty() {
+        let work_dir = config.work_dir(&packages_path)?;
         println!("⚙️  Running update command: {}", config.update_command);
-        match Command::new("sh")
-            .args(&["-c", &config.update_command])
-            .current_dir(&config_dir)
-            .output() 
-        {
-            Ok(output) => {
-                if output.status.success() {
-                    println!("✨ Update command completed successfully");
-                } else {
-                    let error = String::from_utf8_lossy(&output.stderr);
-                    println!("⚠️  Update command failed: {}", error.red());
-                }
-            }
-            Err(e) => {
-                println!("⚠️  Failed to run update command: {}", e.to_string().red());
-            }
+        let output = runner.output(&Cmd::shell(&config.update_command).current_dir(&work_dir))
+            .with_context(|| Error::HookFailed {
+                command: config.update_command.clone(),
+                status: -1,
+            })?;
+
+        if !output.success() {
+            println!("⚠️  Update command failed: {}", output.stderr.red());
+            return Err(Error::HookFailed {
+                command: config.update_command.clone(),
+                status: output.status,
+            }.into());
         }
+        println!("✨ Update command completed successfully");
     }
 
     // If auto_commit is enabled and there are changes, commit them
     if config.auto_commit {
-        // Check if there are any changes
-        if let Ok(output) = Command::new("git")
-            .args(&["status", "--porcelain"])
-            .current_dir(&config_dir)
-            .output() 
-        {
-            if !output.stdout.is_empty() {
-                println!("📝 Committing changes...");
-                
-                // Stage all changes
-                if let Ok(_) = Command::new("git")
-                    .args(&["add", "."])
-                    .current_dir(&config_dir)
-                    .output() 
-                {
-                    // Commit changes
-                    if let Ok(_) = Command::new("git")
-                        .args(&["commit", "-m", "chore: update packages"])
-                        .current_dir(&config_dir)
-                        .output() 
-                    {
-                        println!("✨ Changes committed");
-
-                        // Push if auto_push is enabled
-                        if config.auto_push {
-                            if let Ok(_) = Command::new("git")
-                                .args(&["push"])
-                                .current_dir(&config_dir)
-                                .output() 
-                            {
-                                println!("🚀 Changes pushed to remote");
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        let repo = config.repo_root(&packages_path)?;
+        let updated: Vec<PathBuf> = git::changed_files(&repo, &[])?
+            .into_iter()
+            .filter(|file| !dirty.contains(file))
+            .map(|file| repo.join(file))
+            .collect();
+        let new_revisions = flake::locked_revisions(&lock_file).unwrap_or_default();
+        let context = MessageContext {
+            host: config.host_name(),
+            inputs: flake::changes(&old_revisions, &new_revisions),
+            ..MessageContext::default()
+        };
+        commit_changes(config, runner, &repo, &updated, &context, &config.update_message)?;
     }
 
     println!("✅ Package update complete!");
     Ok(())
 }
 
-// Helper function for running shell commands
-pub fn run_command(command: &str) -> Result<()> {
-    Command::new("sh")
-        .args(&["-c", command])
-        .status()
-        .context(format!("Failed to run command: {}", command))?;
+/// Show the commands an update would run, without running them.
+fn preview_update(config: &Config) -> Result<()> {
+    let packages_path = config.packages_path()?;
+
+    let mut commands: Vec<String> = pull_plan(config).into_iter().collect();
+    commands.extend(hook_plan(config, &config.update_command, &packages_path)?);
+    if config.auto_commit {
+        let repo = config.repo_root(&packages_path)?;
+        let lock_file = flake_lock(config, &packages_path)?;
+        git::ensure_clean(&repo, &[packages_path.clone(), lock_file.clone()])?;
+        let context = MessageContext {
+            host: config.host_name(),
+            ..MessageContext::default()
+        };
+        commands.extend(commit_plan(config, &repo, &[lock_file], &context, &config.update_message)?);
+    }
+
+    print_plan(&[], &commands);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use yuki::runner::{FakeRunner, Output};
+
+    use crate::commands::testing::{self, committed_files, log};
+
+    fn lock_file(rev: &str) -> String {
+        format!(r#"{{"nodes": {{"nixpkgs": {{"locked": {{"rev": "{}"}}}}, "root": {{"inputs": {{"nixpkgs": "nixpkgs"}}}}}}, "root": "root"}}"#, rev)
+    }
+
+    /// Runs the update command by rewriting the flake lock, like `nix flake
+    /// update` would, and records it in a [`FakeRunner`].
+    struct Updater {
+        fake: FakeRunner,
+        lock: PathBuf,
+    }
+
+    impl CommandRunner for Updater {
+        fn output(&self, cmd: &Cmd) -> Result<Output> {
+            fs::write(&self.lock, lock_file("bbbbbbbbbb")).unwrap();
+            self.fake.output(cmd)
+        }
+
+        fn stream(&self, cmd: &Cmd) -> Result<Output> {
+            self.fake.stream(cmd)
+        }
+
+        fn interactive(&self, cmd: &Cmd) -> Result<Output> {
+            self.fake.interactive(cmd)
+        }
+    }
+
+    fn repo() -> (tempfile::TempDir, PathBuf) {
+        testing::repo(&[
+            ("apps.nix", "{ pkgs, ... }:\n{\n  environment.systemPackages = with pkgs; [ git ];\n}\n"),
+            ("flake.lock", &lock_file("aaaaaaaaaa")),
+        ])
+    }
+
+    fn config(repo: &Path) -> Config {
+        Config {
+            update_message: "chore: update\n\n<inputs>".to_string(),
+            ..testing::config(&repo.join("apps.nix"))
+        }
+    }
+
+    #[test]
+    fn commits_only_what_the_update_changed() {
+        let (_dir, repo) = repo();
+        fs::write(repo.join("notes.txt"), "work in progress\n").unwrap();
+        let runner = Updater { fake: FakeRunner::new(), lock: repo.join("flake.lock") };
+
+        update_packages(&config(&repo), &runner).unwrap();
+
+        assert_eq!(runner.fake.command_lines(), ["sh -c make update"]);
+        assert_eq!(log(&repo), ["chore: update", "init"]);
+        assert_eq!(committed_files(&repo), ["flake.lock"]);
+        let message = git2::Repository::open(&repo).unwrap().head().unwrap().peel_to_commit().unwrap().message().unwrap().to_string();
+        assert!(message.ends_with("nixpkgs: aaaaaaa → bbbbbbb"), "{}", message);
+        assert_eq!(git::changed_files(&repo, &[]).unwrap(), [PathBuf::from("notes.txt")]);
+    }
+
+    #[test]
+    fn reads_inputs_of_flake_above_package_file() {
+        let (_dir, repo) = testing::repo(&[
+            ("nixos/flake.nix", "{ }\n"),
+            ("nixos/flake.lock", &lock_file("aaaaaaaaaa")),
+            ("nixos/apps.nix", "{ pkgs, ... }:\n{\n  environment.systemPackages = with pkgs; [ git ];\n}\n"),
+        ]);
+        let runner = Updater { fake: FakeRunner::new(), lock: repo.join("nixos/flake.lock") };
+        let config = Config {
+            update_message: "chore: update\n\n<inputs>".to_string(),
+            ..testing::config(&repo.join("nixos/apps.nix"))
+        };
+
+        update_packages(&config, &runner).unwrap();
+
+        assert_eq!(committed_files(&repo), ["nixos/flake.lock"]);
+        let message = git2::Repository::open(&repo).unwrap().head().unwrap().peel_to_commit().unwrap().message().unwrap().to_string();
+        assert!(message.ends_with("nixpkgs: aaaaaaa → bbbbbbb"), "{}", message);
+    }
+
+    #[test]
+    fn dry_run_reports_uncommitted_changes() {
+        let (_dir, repo) = repo();
+        fs::write(repo.join("flake.lock"), lock_file("bbbbbbbbbb")).unwrap();
+        let runner = FakeRunner::new();
+
+        let e = update_packages(&Config { dry_run: true, ..config(&repo) }, &runner).unwrap_err();
+
+        assert_eq!(Error::exit_code_of(&e), 10);
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    fn failed_update_command_commits_nothing() {
+        let (_dir, repo) = repo();
+        let runner = FakeRunner::new();
+        runner.on("sh -c", Output::err(1, "error: unable to download"));
+
+        let e = update_packages(&config(&repo), &runner).unwrap_err();
+
+        assert_eq!(Error::exit_code_of(&e), 9);
+        assert_eq!(log(&repo), ["init"]);
+    }
+}
@@ -1,108 +1,432 @@
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     fs,
     path::{Path, PathBuf},
+    time::Duration,
 };
-use anyhow::{Result, Context};
+use anyhow::{Context, Result};
+use crate::error::Error;
+use crate::git;
+use crate::platform::Platform;
+
+use super::legacy;
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
+    pub platform: Platform,
     pub linux_packages_path: String,
     pub darwin_packages_path: String,
     pub homebrew_packages_path: String,
     pub auto_commit: bool,
     pub auto_push: bool,
+    /// Fetch and update the current branch before changing package files
+    pub auto_pull: bool,
+    /// Rebase local commits when pulling into a diverged branch, instead of
+    /// failing
+    pub pull_rebase: bool,
+    /// Fail the command if pushing fails, instead of only warning
+    pub push_failure_fatal: bool,
+    /// Commit to a new branch (pushed for review with `auto_push`) instead of
+    /// committing to the current branch
+    pub review_branch: bool,
+    /// Template for the name of review branches
+    pub review_branch_name: String,
+    /// Command run after a review branch was pushed, e.g. to open a pull request
+    pub review_command: String,
     pub uninstall_message: String,
     pub install_message: String,
+    pub update_message: String,
+    /// Check that a package exists before adding it to a package file
+    pub verify_packages: bool,
     pub install_command: String,
     pub uninstall_command: String,
     pub update_command: String,
+    /// Keep (and commit) a change even if the install or uninstall command
+    /// fails, instead of rolling it back
+    pub keep_on_failure: bool,
+    /// Root of the dotfiles repository, found from the package file if empty
+    pub repo_path: String,
+    /// Seconds to wait for another yuki process to finish its changes
+    pub lock_timeout: u64,
+    /// Named host profiles, see [`Host`]
+    pub hosts: BTreeMap<String, Host>,
+    /// Name of the host profile in use, if any
+    #[serde(skip)]
+    pub host: Option<String>,
+    /// Only show what would change, see [`Overrides::dry_run`]
+    #[serde(skip)]
+    pub dry_run: bool,
+}
+
+/// Package files of one machine. A profile is used when `--host` names it or
+/// when the machine's hostname matches; unset fields fall back to the
+/// top-level settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Host {
+    /// Hostname that selects this profile, defaults to the profile name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    /// Nix packages file of the host
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packages_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homebrew_packages_path: Option<String>,
+}
+
+/// Settings for a single run that take precedence over both the config file
+/// and `YUKI_*` environment variables, usually taken from command line flags.
+#[derive(Debug, Default, Clone)]
+pub struct Overrides {
+    /// Config file to use instead of the default one
+    pub config_path: Option<PathBuf>,
+    /// Host profile to use instead of the one matching the hostname
+    pub host: Option<String>,
+    pub platform: Option<Platform>,
+    /// Nix packages file to edit instead of the platform's configured one
+    pub packages_file: Option<PathBuf>,
+    pub no_commit: bool,
+    pub no_push: bool,
+    /// Don't run the install, uninstall or update command
+    pub no_hook: bool,
+    /// Don't check that packages exist before adding them
+    pub no_verify: bool,
+    pub keep_on_failure: bool,
+    /// Print the changes and commands a command would make instead of
+    /// making them
+    pub dry_run: bool,
+}
+
+/// Name of the environment variable that overrides config `key`.
+pub fn env_var(key: &str) -> String {
+    format!("YUKI_{}", key.to_uppercase())
 }
 
 impl Config {
+    /// Load the config file, then apply `YUKI_*` environment variables.
     pub fn load() -> Result<Self> {
-        let config_path = Self::get_config_path()?;
-        
-        if !config_path.exists() {
-            Self::create_default_config(&config_path)?;
-        }
-        
-        let config_str = fs::read_to_string(&config_path)
-            .context("Failed to read config file")?;
-            
-        // Parse TOML-style config
-        let mut config = Self::default();
-        for line in config_str.lines() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('#') {
-                continue;
+        Self::load_with(&Overrides::default())
+    }
+
+    /// Load the config file and the host profile in use, then apply `YUKI_*`
+    /// environment variables and finally `overrides`.
+    pub fn load_with(overrides: &Overrides) -> Result<Self> {
+        let path = Self::ensure_config(overrides.config_path.as_deref())?;
+        let mut config = Self::load_from(&path)?;
+
+        match &overrides.host {
+            Some(name) => config.select_host(name)?,
+            None => {
+                if let Some(name) = config.host_for(&current_hostname()) {
+                    config.select_host(&name)?;
+                }
+            },
+        }
+        let mut config = config.apply_env()?;
+
+        if let Some(platform) = overrides.platform {
+            config.platform = platform;
+        }
+        if let Some(packages_file) = &overrides.packages_file {
+            let packages_file = packages_file.to_string_lossy().into_owned();
+            match config.platform {
+                Platform::Darwin => config.darwin_packages_path = packages_file,
+                Platform::Linux => config.linux_packages_path = packages_file,
             }
-            
-            let parts: Vec<&str> = line.splitn(2, ' ').collect();
-            if parts.len() != 2 {
-                continue;
+        }
+        if overrides.no_commit {
+            config.auto_commit = false;
+        }
+        if overrides.no_push {
+            config.auto_push = false;
+        }
+        if overrides.no_hook {
+            config.install_command.clear();
+            config.uninstall_command.clear();
+            config.update_command.clear();
+        }
+        if overrides.no_verify {
+            config.verify_packages = false;
+        }
+        if overrides.keep_on_failure {
+            config.keep_on_failure = true;
+        }
+        config.dry_run = overrides.dry_run;
+
+        Ok(config)
+    }
+
+    /// Path of the config file. `path`, then `YUKI_CONFIG`, take precedence
+    /// over the default location and must exist; the default config is
+    /// created (or migrated from a legacy config) if it doesn't exist yet.
+    pub fn ensure_config(path: Option<&Path>) -> Result<PathBuf> {
+        let explicit = path.map(Path::to_path_buf)
+            .or_else(|| std::env::var_os("YUKI_CONFIG").map(PathBuf::from));
+
+        if let Some(config_path) = explicit {
+            let config_path = PathBuf::from(shellexpand::tilde(&config_path.to_string_lossy()).into_owned());
+            if !config_path.is_file() {
+                return Err(Error::ConfigInvalid(format!("config file {} does not exist", config_path.display())).into());
             }
-            
-            let key = parts[0];
-            let value = parts[1];
-            
-            match key {
-                "linux_packages_path" => config.linux_packages_path = value.to_string(),
-                "darwin_packages_path" => config.darwin_packages_path = value.to_string(),
-                "homebrew_packages_path" => config.homebrew_packages_path = value.to_string(),
-                "auto_commit" => config.auto_commit = value.parse().unwrap_or(false),
-                "auto_push" => config.auto_push = value.parse().unwrap_or(false),
-                "uninstall_message" => config.uninstall_message = value.trim_matches('"').to_string(),
-                "install_message" => config.install_message = value.trim_matches('"').to_string(),
-                "install_command" => config.install_command = value.trim_matches('"').to_string(),
-                "uninstall_command" => config.uninstall_command = value.trim_matches('"').to_string(),
-                "update_command" => config.update_command = value.trim_matches('"').to_string(),
-                _ => {}
+            return Ok(config_path);
+        }
+
+        let config_path = Self::get_config_path()?;
+
+        if !config_path.exists() {
+            match legacy::find() {
+                Some(legacy_path) => Self::migrate(&legacy_path, &config_path)?,
+                None => Self::create_default_config(&config_path)?,
             }
         }
-        
-        Ok(config)
+
+        Ok(config_path)
     }
 
-    pub fn get_config_path() -> Result<PathBuf> {
-        // Try ~/.yukirc first
-        if let Some(home) = dirs::home_dir() {
-            let yukirc = home.join(".yukirc");
-            if yukirc.exists() {
-                return Ok(yukirc);
+    /// Name of the host profile matching `hostname`.
+    pub fn host_for(&self, hostname: &str) -> Option<String> {
+        self.hosts.iter()
+            .find(|(name, host)| host.hostname.as_deref().unwrap_or(name).eq_ignore_ascii_case(hostname))
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Manage the package files of host profile `name`.
+    pub fn select_host(&mut self, name: &str) -> Result<()> {
+        let Some(host) = self.hosts.get(name).cloned() else {
+            let known: Vec<&str> = self.hosts.keys().map(String::as_str).collect();
+            let known = if known.is_empty() { "none".to_string() } else { known.join(", ") };
+            return Err(Error::ConfigInvalid(format!("unknown host '{}' (configured hosts: {})", name, known)).into());
+        };
+
+        if let Some(platform) = host.platform {
+            self.platform = platform;
+        }
+        if let Some(packages_path) = host.packages_path {
+            match self.platform {
+                Platform::Darwin => self.darwin_packages_path = packages_path,
+                Platform::Linux => self.linux_packages_path = packages_path,
             }
         }
-        // Try ~/.config/yuki/config.conf
-        if let Some(config_dir) = dirs::config_dir() {
-            let yuki_config = config_dir.join("yuki");
-            fs::create_dir_all(&yuki_config)?;
-            return Ok(yuki_config.join("config.conf"));
+        if let Some(homebrew_packages_path) = host.homebrew_packages_path {
+            self.homebrew_packages_path = homebrew_packages_path;
+        }
+        self.host = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Replace values with those of the matching `YUKI_*` environment
+    /// variables, e.g. `YUKI_AUTO_COMMIT=false`.
+    pub fn apply_env(self) -> Result<Self> {
+        let mut table = toml::Table::try_from(&self).context("Failed to serialize config")?;
+        let keys: Vec<String> = table.keys().cloned().collect();
+
+        for key in keys {
+            let var = env_var(&key);
+            let Ok(raw) = std::env::var(&var) else {
+                continue;
+            };
+
+            let value = match table[&key] {
+                toml::Value::Table(_) => continue,
+                toml::Value::Boolean(_) => match raw.trim().to_lowercase().as_str() {
+                    "true" | "1" => toml::Value::Boolean(true),
+                    "false" | "0" => toml::Value::Boolean(false),
+                    _ => return Err(Error::ConfigInvalid(format!("{} expects true or false, got '{}'", var, raw)).into()),
+                },
+                toml::Value::Integer(_) => raw.trim().parse().map(toml::Value::Integer)
+                    .map_err(|_| Error::ConfigInvalid(format!("{} expects a number, got '{}'", var, raw)))?,
+                _ => toml::Value::String(raw.clone()),
+            };
+            table.insert(key, value);
+
+            // Validate as we go so the error names the offending variable
+            toml::Value::Table(table.clone()).try_into::<Self>()
+                .map_err(|e| Error::ConfigInvalid(format!("{}: {}", var, e.message())))?;
         }
-        Err(anyhow::anyhow!("Could not determine config path"))
+
+        let config: Self = toml::Value::Table(table).try_into()
+            .map_err(|e: toml::de::Error| Error::ConfigInvalid(e.message().to_string()))?;
+        // Settings that aren't serialized survive the round trip
+        Ok(Self {
+            host: self.host,
+            dry_run: self.dry_run,
+            ..config
+        })
+    }
+
+    /// Load and validate the TOML config at `path`.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let config_str = fs::read_to_string(path)
+            .map_err(|e| Error::ConfigInvalid(format!("Failed to read {}: {}", path.display(), e)))?;
+
+        Self::parse(&config_str)
+            .map_err(|e| Error::ConfigInvalid(format!("{}:{}", path.display(), e)).into())
+    }
+
+    /// Parse a TOML config. Errors start with the line number and name the
+    /// offending key.
+    pub fn parse(config_str: &str) -> std::result::Result<Self, String> {
+        toml::from_str(config_str).map_err(|e| {
+            let message = e.message().to_string();
+            let Some(span) = e.span() else {
+                return format!(" {}", message);
+            };
+
+            let line = config_str[..span.start].matches('\n').count() + 1;
+            let key = config_str.lines()
+                .nth(line - 1)
+                .and_then(|l| l.split_once('='))
+                .map(|(key, _)| key.trim().to_string());
+
+            match key {
+                Some(key) if !key.is_empty() => format!("{}: key '{}': {}", line, key, message),
+                _ => format!("{}: {}", line, message),
+            }
+        })
+    }
+
+    pub fn get_config_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir()
+            .ok_or_else(|| anyhow::anyhow!("Could not determine config path"))?;
+        let yuki_config = config_dir.join("yuki");
+        fs::create_dir_all(&yuki_config)?;
+        Ok(yuki_config.join("config.toml"))
+    }
+
+    /// Directory for data yuki keeps between runs, like backups of package
+    /// files: `YUKI_STATE_DIR` if set, otherwise `~/.local/state/yuki` on
+    /// Linux.
+    pub fn get_state_dir() -> Result<PathBuf> {
+        let yuki_state = match std::env::var_os("YUKI_STATE_DIR").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => dirs::state_dir()
+                .or_else(dirs::data_local_dir)
+                .ok_or_else(|| anyhow::anyhow!("Could not determine state directory"))?
+                .join("yuki"),
+        };
+        fs::create_dir_all(&yuki_state)
+            .with_context(|| format!("Failed to create {}", yuki_state.display()))?;
+        Ok(yuki_state)
+    }
+
+    /// Convert a legacy `key value` config to TOML. The old file is kept with
+    /// a `.bak` suffix.
+    fn migrate(legacy_path: &Path, path: &Path) -> Result<()> {
+        let legacy_str = fs::read_to_string(legacy_path)
+            .with_context(|| format!("Failed to read {}", legacy_path.display()))?;
+        let config = legacy::parse(&legacy_str)
+            .map_err(|e| Error::ConfigInvalid(format!("{}:{}", legacy_path.display(), e)))?;
+
+        fs::write(path, config.to_toml())?;
+
+        let mut backup = legacy_path.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::rename(legacy_path, &backup)?;
+
+        eprintln!(
+            "Migrated {} to {} (old file kept as {})",
+            legacy_path.display(),
+            path.display(),
+            PathBuf::from(backup).display()
+        );
+        Ok(())
     }
 
     pub fn create_default_config(path: &Path) -> Result<()> {
-        let config = r#"# Path to linux system packages nix file 
-linux_packages_path ~/dotfiles/hosts/nixos/apps.nix
-# Path to darwin system packages nix file 
-darwin_packages_path ~/dotfiles/hosts/darwin/apps.nix
-homebrew_packages_path ~/dotfiles/hosts/darwin/apps.nix
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, Self::default().to_toml())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Render the config as commented TOML.
+    pub fn to_toml(&self) -> String {
+        let platform = if self.platform == Platform::detect() {
+            "auto".to_string()
+        } else {
+            self.platform.to_string()
+        };
+        let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+
+        format!(r#"# Platform whose package files are managed (darwin, linux or auto)
+platform = {}
+# Path to linux system packages nix file
+linux_packages_path = {}
+# Path to darwin system packages nix file
+darwin_packages_path = {}
+homebrew_packages_path = {}
 # Git setup
 # Automatically add a commit when installing or uninstalling packages
-auto_commit true
-auto_push false
-# Uninstall and install message. Use <package> to insert the package name
-uninstall_message "removed <package>"
-install_message "installed <package>"
+auto_commit = {}
+auto_push = {}
+# Fetch and fast-forward the repository before changing package files
+auto_pull = {}
+# Rebase local commits onto the upstream if the branch has diverged
+pull_rebase = {}
+# Fail when pushing fails. If false, yuki only warns and keeps the local commit
+push_failure_fatal = {}
+# Commit to a new branch and switch back, instead of committing to the current branch. With auto_push the branch is pushed for review
+review_branch = {}
+# Name of review branches. Takes the same placeholders as the commit messages
+review_branch_name = {}
+# Command run after a review branch was pushed. <branch> is the review branch, <base> the original one
+review_command = {}
+# Commit messages. Placeholders: <package>, <packages>, <source>, <host>, <file>, <version>
+# and, for updates, <inputs> (the changed flake inputs, one per line)
+uninstall_message = {}
+install_message = {}
+update_message = {}
+# Check that nixpkgs attributes exist (with nix eval) before adding them
+verify_packages = {}
 # This is the command that will be run after your package has been added to the package config
-install_command "make"
+install_command = {}
 # This is the command that will be run after your package has been removed from the package config
-uninstall_command "make"
+uninstall_command = {}
 # This is the command that will be run to update your packages
-update_command "make update""#;
-        
-        fs::write(path, config)?;
-        Ok(())
+update_command = {}
+# Keep and commit a change even if the install or uninstall command fails, instead of rolling it back
+keep_on_failure = {}
+# Root of your dotfiles repository. Found from the package file when empty
+repo_path = {}
+# Seconds to wait for another running yuki to finish before giving up
+lock_timeout = {}
+
+# Host profiles, picked by hostname or with --host. Unset keys fall back to the settings above
+# [hosts.macbook]
+# hostname = "work-mbp"
+# platform = "darwin"
+# packages_path = "~/dotfiles/hosts/macbook/apps.nix"
+# homebrew_packages_path = "~/dotfiles/hosts/macbook/apps.nix"
+"#,
+            quote(&platform),
+            quote(&self.linux_packages_path),
+            quote(&self.darwin_packages_path),
+            quote(&self.homebrew_packages_path),
+            self.auto_commit,
+            self.auto_push,
+            self.auto_pull,
+            self.pull_rebase,
+            self.push_failure_fatal,
+            self.review_branch,
+            quote(&self.review_branch_name),
+            quote(&self.review_command),
+            quote(&self.uninstall_message),
+            quote(&self.install_message),
+            quote(&self.update_message),
+            self.verify_packages,
+            quote(&self.install_command),
+            quote(&self.uninstall_command),
+            quote(&self.update_command),
+            self.keep_on_failure,
+            quote(&self.repo_path),
+            self.lock_timeout,
+        )
     }
 
     pub fn get_expanded_path(&self, path: &str) -> Result<PathBuf> {
@@ -110,18 +434,171 @@ update_command "make update""#;
         Ok(PathBuf::from(expanded.into_owned()))
     }
 
+    /// Name of the host whose packages are managed: the host profile in use,
+    /// or this machine's hostname.
+    pub fn host_name(&self) -> String {
+        self.host.clone().unwrap_or_else(current_hostname)
+    }
+
+    /// Root of the git repository holding `file`: `repo_path` if set,
+    /// otherwise the repository found by walking up from `file`.
+    pub fn repo_root(&self, file: &Path) -> Result<PathBuf> {
+        if !self.repo_path.is_empty() {
+            return self.get_expanded_path(&self.repo_path);
+        }
+
+        git::find_root(file).ok_or_else(|| {
+            Error::GitFailed(format!("repository not found above {}, set repo_path in the config", file.display())).into()
+        })
+    }
+
+    /// Directory hook commands run in after `file` changed: the repository
+    /// root, or the file's own directory if it isn't in a repository.
+    pub fn work_dir(&self, file: &Path) -> Result<PathBuf> {
+        self.repo_root(file).or_else(|_| {
+            file.parent()
+                .map(Path::to_path_buf)
+                .ok_or_else(|| anyhow::anyhow!("Could not determine directory of {}", file.display()))
+        })
+    }
+
+    /// How long to wait for the lock held by another yuki process.
+    pub fn lock_timeout(&self) -> Duration {
+        Duration::from_secs(self.lock_timeout)
+    }
+
+    /// The Nix packages file for the configured platform.
+    pub fn packages_path(&self) -> Result<PathBuf> {
+        match self.platform {
+            Platform::Darwin => self.get_expanded_path(&self.darwin_packages_path),
+            Platform::Linux => self.get_expanded_path(&self.linux_packages_path),
+        }
+    }
+}
+
+impl Default for Config {
     fn default() -> Self {
         Self {
+            platform: Platform::detect(),
             linux_packages_path: "~/dotfiles/hosts/nixos/apps.nix".to_string(),
             darwin_packages_path: "~/dotfiles/hosts/darwin/apps.nix".to_string(),
             homebrew_packages_path: "~/dotfiles/hosts/darwin/apps.nix".to_string(),
             auto_commit: true,
             auto_push: false,
+            auto_pull: false,
+            pull_rebase: false,
+            push_failure_fatal: true,
+            review_branch: false,
+            review_branch_name: "yuki/<host>/<packages>".to_string(),
+            review_command: String::new(),
             uninstall_message: "removed <package>".to_string(),
             install_message: "installed <package>".to_string(),
+            update_message: "chore: update packages".to_string(),
+            verify_packages: true,
             install_command: "make".to_string(),
             uninstall_command: "make".to_string(),
-            update_command: "make".to_string(),
+            update_command: "make update".to_string(),
+            keep_on_failure: false,
+            repo_path: String::new(),
+            lock_timeout: 60,
+            hosts: BTreeMap::new(),
+            host: None,
+            dry_run: false,
+        }
+    }
+}
+
+/// Hostname of this machine, without the domain.
+pub fn current_hostname() -> String {
+    let hostname = gethostname::gethostname().to_string_lossy().into_owned();
+    match hostname.split_once('.') {
+        Some((short, _)) => short.to_string(),
+        None => hostname,
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Tests that set `YUKI_*` variables hold this, as the environment is
+    /// shared by all tests.
+    pub(crate) static ENV: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn parse_errors_name_line_and_key() {
+        let e = Config::parse("auto_commit = true\nauto_push = \"yes\"\n").unwrap_err();
+        assert!(e.starts_with("2: key 'auto_push': "), "{}", e);
+        let e = Config::parse("auto_comit = true\n").unwrap_err();
+        assert!(e.starts_with("1: key 'auto_comit': unknown field"), "{}", e);
+    }
+
+    #[test]
+    fn applies_environment_variables() {
+        let _env = ENV.lock().unwrap();
+        std::env::set_var("YUKI_AUTO_COMMIT", "0");
+        std::env::set_var("YUKI_LOCK_TIMEOUT", "5");
+        std::env::set_var("YUKI_INSTALL_COMMAND", "make switch");
+        let config = Config::default().apply_env();
+        std::env::set_var("YUKI_LOCK_TIMEOUT", "soon");
+        let invalid = Config::default().apply_env();
+        for var in ["YUKI_AUTO_COMMIT", "YUKI_LOCK_TIMEOUT", "YUKI_INSTALL_COMMAND"] {
+            std::env::remove_var(var);
         }
+
+        let config = config.unwrap();
+        assert!(!config.auto_commit);
+        assert_eq!(config.lock_timeout, 5);
+        assert_eq!(config.install_command, "make switch");
+        assert_eq!(invalid.unwrap_err().to_string(), "Invalid configuration: YUKI_LOCK_TIMEOUT expects a number, got 'soon'");
+    }
+
+    #[test]
+    fn parses_host_profiles() {
+        let config = Config::parse("[hosts.mini]\nhostname = \"Mac-Mini\"\nplatform = \"darwin\"\n").unwrap();
+        assert_eq!(config.host_for("mac-mini").as_deref(), Some("mini"));
+        assert_eq!(config.host_for("mini"), None);
+        assert_eq!(config.hosts["mini"].platform, Some(Platform::Darwin));
+    }
+
+    #[test]
+    fn env_overrides_host_profile() {
+        let _env = ENV.lock().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, r#"
+linux_packages_path = "/base.nix"
+
+[hosts.web]
+packages_path = "/web.nix"
+"#).unwrap();
+        let overrides = Overrides {
+            config_path: Some(path),
+            host: Some("web".to_string()),
+            platform: Some(Platform::Linux),
+            ..Overrides::default()
+        };
+
+        let config = Config::load_with(&overrides).unwrap();
+        assert_eq!(config.linux_packages_path, "/web.nix");
+        assert_eq!(config.host.as_deref(), Some("web"));
+
+        std::env::set_var("YUKI_LINUX_PACKAGES_PATH", "/other.nix");
+        let config = Config::load_with(&overrides);
+        std::env::remove_var("YUKI_LINUX_PACKAGES_PATH");
+        let config = config.unwrap();
+        assert_eq!(config.linux_packages_path, "/other.nix");
+        assert_eq!(config.host.as_deref(), Some("web"));
+    }
+
+    #[test]
+    fn explicit_config_must_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("typo").join("config.toml");
+
+        let e = Config::ensure_config(Some(&path)).unwrap_err();
+        assert_eq!(Error::exit_code_of(&e), 3);
+        assert!(!path.exists());
     }
 }
@@ -0,0 +1,79 @@
+//! The `key value` config format used before the switch to TOML. It is only
+//! read to migrate existing configs.
+
+use std::path::PathBuf;
+
+use super::Config;
+
+/// Location of a legacy config, if one exists. `~/.yukirc` takes precedence
+/// over `~/.config/yuki/config.conf`, as it did when the format was current.
+pub fn find() -> Option<PathBuf> {
+    let yukirc = dirs::home_dir().map(|home| home.join(".yukirc"));
+    let config_conf = dirs::config_dir().map(|dir| dir.join("yuki").join("config.conf"));
+
+    [yukirc, config_conf].into_iter()
+        .flatten()
+        .find(|path| path.exists())
+}
+
+/// Parse a legacy config. Like [`Config::parse`], errors start with the line
+/// number and name the offending key.
+pub fn parse(config_str: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+    for (i, line) in config_str.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parts: Vec<&str> = line.splitn(2, ' ').collect();
+        if parts.len() != 2 {
+            continue;
+        }
+
+        let key = parts[0];
+        let value = parts[1];
+        let invalid = |message: String| format!("{}: key '{}': {}", i + 1, key, message);
+        let boolean = || value.trim().parse::<bool>()
+            .map_err(|_| invalid(format!("expected true or false, got '{}'", value.trim())));
+
+        match key {
+            "platform" => {
+                config.platform = value.trim_matches('"').parse()
+                    .map_err(|e: anyhow::Error| invalid(e.to_string()))?;
+            },
+            "linux_packages_path" => config.linux_packages_path = value.to_string(),
+            "darwin_packages_path" => config.darwin_packages_path = value.to_string(),
+            "homebrew_packages_path" => config.homebrew_packages_path = value.to_string(),
+            "auto_commit" => config.auto_commit = boolean()?,
+            "auto_push" => config.auto_push = boolean()?,
+            "uninstall_message" => config.uninstall_message = value.trim_matches('"').to_string(),
+            "install_message" => config.install_message = value.trim_matches('"').to_string(),
+            "install_command" => config.install_command = value.trim_matches('"').to_string(),
+            "uninstall_command" => config.uninstall_command = value.trim_matches('"').to_string(),
+            "update_command" => config.update_command = value.trim_matches('"').to_string(),
+            _ => {}
+        }
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_legacy_config() {
+        let config = parse("# dotfiles\nlinux_packages_path ~/nix/apps.nix\nauto_commit false\ninstall_message \"add <package>\"\n").unwrap();
+        assert_eq!(config.linux_packages_path, "~/nix/apps.nix");
+        assert!(!config.auto_commit);
+        assert_eq!(config.install_message, "add <package>");
+    }
+
+    #[test]
+    fn rejects_invalid_booleans() {
+        let e = parse("auto_push true\nauto_commit yes\n").unwrap_err();
+        assert_eq!(e, "2: key 'auto_commit': expected true or false, got 'yes'");
+    }
+}
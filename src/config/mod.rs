@@ -1,18 +1,9 @@
 // Re-export modules
+#[allow(clippy::module_inception)]
 pub mod config;
+mod legacy;
+pub mod settings;
 
 // Re-export specific items if needed
-pub use config::Config;
-
-// Any shared types, traits, or functions that are common across modules
-pub type Result<T> = anyhow::Result<T>;
-
-#[derive(thiserror::Error, Debug)]
-pub enum Error {
-    #[error("Configuration error: {0}")]
-    Config(String),
-    #[error("Execution error: {0}")]
-    Execution(String),
-    #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
-}
\ No newline at end of file
+pub use config::{current_hostname, env_var, Config, Host, Overrides};
+pub use settings::{Setting, Source};
@@ -0,0 +1,203 @@
+//! Reading and editing individual config keys, preserving the comments and
+//! layout of the config file.
+
+use anyhow::{Context, Result};
+use std::{fmt, fs, path::Path};
+use toml_edit::DocumentMut;
+use crate::error::Error;
+
+use super::{env_var, Config};
+
+/// Where the effective value of a setting comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File,
+    /// A `YUKI_*` environment variable
+    Env,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File => write!(f, "file"),
+            Source::Env => write!(f, "env"),
+        }
+    }
+}
+
+/// The effective value of a config key.
+#[derive(Debug, Clone)]
+pub struct Setting {
+    pub key: String,
+    pub value: toml::Value,
+    pub source: Source,
+}
+
+fn read_document(path: &Path) -> Result<DocumentMut> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    content.parse::<DocumentMut>()
+        .map_err(|e| Error::ConfigInvalid(format!("{}: {}", path.display(), e)).into())
+}
+
+fn to_table(config: &Config) -> Result<toml::Table> {
+    toml::Table::try_from(config).context("Failed to serialize config")
+}
+
+fn check_key(key: &str) -> Result<()> {
+    if to_table(&Config::default())?.get(key).is_some_and(|value| !value.is_table()) {
+        Ok(())
+    } else {
+        Err(Error::ConfigInvalid(format!("unknown key '{}'", key)).into())
+    }
+}
+
+/// Validate `document` and write it to `path`.
+fn save(path: &Path, document: &DocumentMut) -> Result<()> {
+    let content = document.to_string();
+    Config::parse(&content)
+        .map_err(|e| Error::ConfigInvalid(format!("{}:{}", path.display(), e)))?;
+
+    fs::write(path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Effective values of all top-level config keys in the config at `path`,
+/// including `YUKI_*` environment overrides. Host profiles are left out.
+pub fn list(path: &Path) -> Result<Vec<Setting>> {
+    let config = Config::load_from(path)?.apply_env()?;
+    let document = read_document(path)?;
+
+    Ok(to_table(&config)?
+        .into_iter()
+        .filter(|(_, value)| !value.is_table())
+        .map(|(key, value)| {
+            let source = if std::env::var_os(env_var(&key)).is_some() {
+                Source::Env
+            } else if document.contains_key(&key) {
+                Source::File
+            } else {
+                Source::Default
+            };
+            Setting { key, value, source }
+        })
+        .collect())
+}
+
+/// Effective value of `key` in the config at `path`.
+pub fn get(path: &Path, key: &str) -> Result<Setting> {
+    check_key(key)?;
+    list(path)?
+        .into_iter()
+        .find(|setting| setting.key == key)
+        .ok_or_else(|| Error::ConfigInvalid(format!("unknown key '{}'", key)).into())
+}
+
+/// Set `key` to `value` in the config at `path`. The value is converted to the
+/// key's type and the resulting config is validated before it's saved.
+pub fn set(path: &Path, key: &str, value: &str) -> Result<()> {
+    check_key(key)?;
+    let mut document = read_document(path)?;
+
+    let item = match to_table(&Config::default())?.get(key) {
+        Some(toml::Value::Boolean(_)) => {
+            let value: bool = value.parse()
+                .map_err(|_| Error::ConfigInvalid(format!("key '{}' expects true or false, got '{}'", key, value)))?;
+            toml_edit::value(value)
+        },
+        Some(toml::Value::Integer(_)) => {
+            let value: i64 = value.parse()
+                .map_err(|_| Error::ConfigInvalid(format!("key '{}' expects a number, got '{}'", key, value)))?;
+            toml_edit::value(value)
+        },
+        _ => toml_edit::value(value),
+    };
+    document[key] = item;
+
+    save(path, &document)
+}
+
+/// Remove `key` from the config at `path`, reverting it to its default.
+pub fn unset(path: &Path, key: &str) -> Result<()> {
+    check_key(key)?;
+    let mut document = read_document(path)?;
+    document.remove(key);
+    save(path, &document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::config::tests::ENV;
+
+    const CONFIG: &str = "# yuki settings\n\n# commit every change\nauto_commit = true\ninstall_command = \"make switch\" # rebuild\n";
+
+    fn config_file(content: &str) -> (tempfile::TempDir, std::path::PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+
+    fn setting<'a>(settings: &'a [Setting], key: &str) -> &'a Setting {
+        settings.iter().find(|setting| setting.key == key).unwrap()
+    }
+
+    #[test]
+    fn set_keeps_comments() {
+        let (_dir, path) = config_file(CONFIG);
+
+        set(&path, "auto_commit", "false").unwrap();
+        set(&path, "lock_timeout", "5").unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# yuki settings\n\n# commit every change\nauto_commit = false\n"), "{}", content);
+        assert!(content.contains("# rebuild"), "{}", content);
+        let config = Config::load_from(&path).unwrap();
+        assert!(!config.auto_commit);
+        assert_eq!(config.lock_timeout, 5);
+    }
+
+    #[test]
+    fn rejects_invalid_values_before_saving() {
+        let (_dir, path) = config_file(CONFIG);
+
+        for (key, value) in [("auto_commit", "yes"), ("lock_timeout", "soon"), ("platform", "windows"), ("auto_comit", "true")] {
+            let e = set(&path, key, value).unwrap_err();
+            assert_eq!(Error::exit_code_of(&e), 3, "{} = {}: {:#}", key, value, e);
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), CONFIG);
+    }
+
+    #[test]
+    fn unset_reverts_to_default() {
+        let (_dir, path) = config_file(CONFIG);
+
+        unset(&path, "install_command").unwrap();
+
+        // get() reads YUKI_* variables, which another test sets
+        let _env = ENV.lock().unwrap();
+        let setting = get(&path, "install_command").unwrap();
+        assert_eq!(setting.value, toml::Value::String(Config::default().install_command));
+        assert_eq!(setting.source, Source::Default);
+        assert!(fs::read_to_string(&path).unwrap().contains("# commit every change\nauto_commit = true"));
+    }
+
+    #[test]
+    fn lists_where_values_come_from() {
+        let (_dir, path) = config_file(CONFIG);
+
+        let _env = ENV.lock().unwrap();
+        std::env::set_var("YUKI_INSTALL_COMMAND", "make test");
+        let settings = list(&path);
+        std::env::remove_var("YUKI_INSTALL_COMMAND");
+        let settings = settings.unwrap();
+
+        assert_eq!(setting(&settings, "auto_commit").source, Source::File);
+        let install = setting(&settings, "install_command");
+        assert_eq!((&install.value, install.source), (&toml::Value::String("make test".to_string()), Source::Env));
+        assert_eq!(setting(&settings, "auto_push").source, Source::Default);
+    }
+}
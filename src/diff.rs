@@ -0,0 +1,35 @@
+use colored::*;
+use similar::TextDiff;
+use std::path::Path;
+
+/// Unified diff from `old` to `new`, with `path` as the file name in the
+/// header.
+pub fn unified(path: &Path, old: &str, new: &str) -> String {
+    let name = path.display().to_string();
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&name, &name)
+        .to_string()
+}
+
+/// [`unified`] with added lines in green, removed ones in red and hunk
+/// headers in cyan.
+pub fn colored(path: &Path, old: &str, new: &str) -> String {
+    unified(path, old, new)
+        .lines()
+        .map(|line| {
+            if line.starts_with("+++") || line.starts_with("---") {
+                line.bold().to_string()
+            } else if line.starts_with('+') {
+                line.green().to_string()
+            } else if line.starts_with('-') {
+                line.red().to_string()
+            } else if line.starts_with("@@") {
+                line.cyan().to_string()
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
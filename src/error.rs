@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+/// Errors yuki reports to its callers. Each variant maps to a distinct
+/// process exit code, see [`Error::exit_code`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Invalid configuration: {0}")]
+    ConfigInvalid(String),
+    #[error("Package file not found at: {}", .0.display())]
+    PackageFileMissing(PathBuf),
+    #[error("Could not find {attr} in {}", file.display())]
+    AttributeNotFound { attr: String, file: PathBuf },
+    #[error("Package {0} is already installed")]
+    AlreadyPresent(String),
+    #[error("Package {0} is not installed")]
+    NotFound(String),
+    #[error("{package} does not exist in {backend}{}", did_you_mean(suggestions))]
+    UnknownPackage { package: String, backend: String, suggestions: Vec<String> },
+    #[error("{name} is not available: {reason}")]
+    BackendUnavailable { name: String, reason: String },
+    #[error("{command} failed with status {status}")]
+    HookFailed { command: String, status: i32 },
+    #[error("git {0}")]
+    GitFailed(String),
+    #[error("Refusing to write {}: {reason}\n{diff}", file.display())]
+    InvalidEdit { file: PathBuf, reason: String, diff: String },
+    #[error("Another yuki process (pid {pid}) is still running, gave up waiting after {seconds}s")]
+    Locked { pid: String, seconds: u64 },
+    #[error("{} has uncommitted changes, commit or stash them first or pass --no-commit", .0.display())]
+    UncommittedChanges(PathBuf),
+}
+
+impl Error {
+    /// Exit code of the `yuki` binary when a command fails with this error.
+    ///
+    /// | Code | Meaning                    |
+    /// |------|----------------------------|
+    /// | 1    | Any other error            |
+    /// | 2    | Invalid command line usage |
+    /// | 3    | Invalid configuration      |
+    /// | 4    | Package file missing       |
+    /// | 5    | Attribute not found        |
+    /// | 6    | Package already installed  |
+    /// | 7    | Package not installed      |
+    /// | 8    | Backend unavailable        |
+    /// | 9    | Hook command failed        |
+    /// | 10   | Git failure or dirty file  |
+    /// | 11   | Unknown package            |
+    /// | 12   | Edit would break the file  |
+    /// | 13   | Another yuki is running    |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::ConfigInvalid(_) => 3,
+            Error::PackageFileMissing(_) => 4,
+            Error::AttributeNotFound { .. } => 5,
+            Error::AlreadyPresent(_) => 6,
+            Error::NotFound(_) => 7,
+            Error::BackendUnavailable { .. } => 8,
+            Error::HookFailed { .. } => 9,
+            Error::GitFailed(_) | Error::UncommittedChanges(_) => 10,
+            Error::UnknownPackage { .. } => 11,
+            Error::InvalidEdit { .. } => 12,
+            Error::Locked { .. } => 13,
+        }
+    }
+
+    /// Exit code for an arbitrary error, looking through its context chain
+    /// for a yuki [`Error`].
+    pub fn exit_code_of(error: &anyhow::Error) -> i32 {
+        error.chain()
+            .find_map(|e| e.downcast_ref::<Error>())
+            .map_or(1, Error::exit_code)
+    }
+}
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        String::new()
+    } else {
+        format!(", did you mean {}?", suggestions.join(", "))
+    }
+}
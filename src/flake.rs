@@ -0,0 +1,135 @@
+//! Finding the flake a package file belongs to and reading its
+//! `flake.lock`, to describe what an update changed.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// A flake input whose locked revision changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputChange {
+    pub name: String,
+    /// Revision before the change, `None` if the input was added
+    pub old: Option<String>,
+    /// Revision after the change, `None` if the input was removed
+    pub new: Option<String>,
+}
+
+impl fmt::Display for InputChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let short = |rev: &str| rev.chars().take(7).collect::<String>();
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => write!(f, "{}: {} → {}", self.name, short(old), short(new)),
+            (None, Some(new)) => write!(f, "{}: added at {}", self.name, short(new)),
+            (Some(_), None) => write!(f, "{}: removed", self.name),
+            (None, None) => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// The flake `file` belongs to: the nearest directory above it containing a
+/// `flake.nix`. A symlinked file is looked up from its target.
+pub fn find_dir(file: &Path) -> Option<PathBuf> {
+    let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    file.ancestors()
+        .skip(1)
+        .find(|dir| dir.join("flake.nix").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Locked revision of every direct input in the lock file at `path`. Inputs
+/// without a git revision (e.g. paths) are identified by their `narHash`.
+pub fn locked_revisions(path: &Path) -> Result<BTreeMap<String, String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let lock: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let nodes = &lock["nodes"];
+    let root = lock["root"].as_str().unwrap_or("root");
+    let Some(inputs) = nodes[root]["inputs"].as_object() else {
+        return Ok(BTreeMap::new());
+    };
+
+    Ok(inputs.iter()
+        // Inputs that `follow` another one are lists, not node names
+        .filter_map(|(name, node)| Some((name, node.as_str()?)))
+        .filter_map(|(name, node)| {
+            let locked = &nodes[node]["locked"];
+            let rev = locked["rev"].as_str().or_else(|| locked["narHash"].as_str())?;
+            Some((name.clone(), rev.to_string()))
+        })
+        .collect())
+}
+
+/// Inputs that differ between two sets of [`locked_revisions`].
+pub fn changes(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> Vec<InputChange> {
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    names.into_iter()
+        .filter(|name| old.get(*name) != new.get(*name))
+        .map(|name| InputChange {
+            name: name.clone(),
+            old: old.get(name).cloned(),
+            new: new.get(name).cloned(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revisions(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(name, rev)| (name.to_string(), rev.to_string())).collect()
+    }
+
+    #[test]
+    fn lists_changed_inputs() {
+        let old = revisions(&[("nixpkgs", "aaa"), ("home-manager", "hhh"), ("old", "ooo")]);
+        let new = revisions(&[("nixpkgs", "bbb"), ("home-manager", "hhh"), ("new", "nnn")]);
+
+        let changes: Vec<String> = changes(&old, &new).iter().map(ToString::to_string).collect();
+        assert_eq!(changes, ["new: added at nnn", "nixpkgs: aaa → bbb", "old: removed"]);
+        assert!(super::changes(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn finds_nearest_flake() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for file in ["flake.nix", "hosts/web/apps.nix", "darwin/flake.nix", "darwin/apps.nix"] {
+            fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+            fs::write(root.join(file), "{ }\n").unwrap();
+        }
+        std::os::unix::fs::symlink(root.join("hosts/web/apps.nix"), dir.path().join("link.nix")).unwrap();
+
+        assert_eq!(find_dir(&root.join("hosts/web/apps.nix")), Some(root.clone()));
+        assert_eq!(find_dir(&root.join("darwin/apps.nix")), Some(root.join("darwin")));
+        assert_eq!(find_dir(&root.join("darwin/missing.nix")), Some(root.join("darwin")));
+        assert_eq!(find_dir(&dir.path().join("link.nix")), Some(root));
+    }
+
+    #[test]
+    fn reads_direct_inputs_of_lock_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flake.lock");
+        fs::write(&path, r#"{
+  "nodes": {
+    "nixpkgs": { "locked": { "rev": "abc" } },
+    "local": { "locked": { "narHash": "sha256-x" } },
+    "root": { "inputs": { "nixpkgs": "nixpkgs", "local": "local", "follower": ["nixpkgs"] } }
+  },
+  "root": "root",
+  "version": 7
+}"#).unwrap();
+
+        assert_eq!(locked_revisions(&path).unwrap(), revisions(&[("local", "sha256-x"), ("nixpkgs", "abc")]));
+    }
+}
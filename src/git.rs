@@ -0,0 +1,665 @@
+//! Git repository operations, done in-process through libgit2 so the `git`
+//! binary isn't needed. Commits use the identity and signing settings from
+//! the user's git config.
+
+use anyhow::{Context, Result};
+use git2::{Cred, CredentialType, PushOptions, RemoteCallbacks, Repository, Status, StatusOptions};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use crate::error::Error;
+use crate::runner::{Cmd, CommandRunner};
+
+/// The outcome of a commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Commit {
+    Created,
+    /// The files were already committed as they are, so no commit was made
+    NothingToCommit,
+}
+
+/// Turn a libgit2 error into a [`Error::GitFailed`] for operation `op`.
+fn failed(op: &'static str) -> impl Fn(git2::Error) -> anyhow::Error {
+    move |e| Error::GitFailed(format!("{} failed: {}", op, e.message())).into()
+}
+
+/// Root of the git repository containing `path`, found by walking up the
+/// directory tree.
+pub fn find_root(path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    Repository::discover(&path).ok()?
+        .workdir()
+        .map(|dir| dir.components().collect())
+}
+
+fn open(repo: &Path) -> Result<Repository> {
+    Repository::open(repo).map_err(failed("open"))
+}
+
+/// `file` relative to the working directory of `repo`.
+fn relative(repo: &Repository, file: &Path) -> Result<PathBuf> {
+    let workdir = repo.workdir()
+        .ok_or_else(|| Error::GitFailed("repository has no working directory".to_string()))?;
+    let workdir = workdir.canonicalize().unwrap_or_else(|_| workdir.to_path_buf());
+
+    // Follow a symlinked file into the repository holding its target. The
+    // file may not exist (anymore), in which case its directory is resolved
+    let file = match (file.canonicalize(), file.parent(), file.file_name()) {
+        (Ok(file), _, _) => file,
+        (_, Some(dir), Some(name)) if !dir.as_os_str().is_empty() => {
+            dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf()).join(name)
+        },
+        _ => file.to_path_buf(),
+    };
+
+    file.strip_prefix(&workdir)
+        .map(Path::to_path_buf)
+        .map_err(|_| Error::GitFailed(format!("{} is outside the repository at {}", file.display(), workdir.display())).into())
+}
+
+/// Paths (relative to `repo`) with uncommitted changes, limited to `files`
+/// unless it's empty.
+pub fn changed_files(repo: &Path, files: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let repo = open(repo)?;
+
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true).disable_pathspec_match(true);
+    for file in files {
+        options.pathspec(relative(&repo, file)?);
+    }
+
+    let statuses = repo.statuses(Some(&mut options)).map_err(failed("status"))?;
+    Ok(statuses.iter()
+        .filter(|entry| entry.status() != Status::CURRENT && !entry.status().is_ignored())
+        .filter_map(|entry| entry.path().map(PathBuf::from))
+        .collect())
+}
+
+/// Fail if any of `files` has uncommitted changes, so that committing them
+/// later doesn't sweep in unrelated work.
+pub fn ensure_clean(repo: &Path, files: &[PathBuf]) -> Result<()> {
+    match changed_files(repo, files)?.into_iter().next() {
+        Some(file) => Err(Error::UncommittedChanges(repo.join(file)).into()),
+        None => Ok(()),
+    }
+}
+
+fn head_commit(repo: &Repository) -> Result<Option<git2::Commit<'_>>> {
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_commit().map_err(failed("commit"))?)),
+        // A new repository without commits
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+        Err(e) => Err(failed("commit")(e)),
+    }
+}
+
+/// Stage and commit exactly `files`, leaving anything else in the index or
+/// working tree alone.
+pub fn commit_files(runner: &dyn CommandRunner, repo: &Path, files: &[PathBuf], message: &str) -> Result<Commit> {
+    let repo = open(repo)?;
+    let parent = head_commit(&repo)?;
+
+    // Build the new tree from HEAD plus the files, so other staged changes
+    // stay out of the commit
+    let mut tree_index = git2::Index::new().map_err(failed("add"))?;
+    if let Some(parent) = &parent {
+        tree_index.read_tree(&parent.tree().map_err(failed("add"))?).map_err(failed("add"))?;
+    }
+    let mut index = repo.index().map_err(failed("add"))?;
+
+    for file in files {
+        let path = relative(&repo, file)?;
+        if repo.workdir().is_some_and(|dir| dir.join(&path).exists()) {
+            index.add_path(&path).map_err(failed("add"))?;
+            let entry = index.get_path(&path, 0)
+                .ok_or_else(|| Error::GitFailed(format!("add failed: {} was not staged", path.display())))?;
+            tree_index.add(&entry).map_err(failed("add"))?;
+        } else {
+            // Tolerate files that were never tracked
+            let _ = index.remove_path(&path);
+            let _ = tree_index.remove_path(&path);
+        }
+    }
+    index.write().map_err(failed("add"))?;
+
+    let tree_id = tree_index.write_tree_to(&repo).map_err(failed("commit"))?;
+    if parent.as_ref().is_some_and(|parent| parent.tree_id() == tree_id) {
+        return Ok(Commit::NothingToCommit);
+    }
+
+    write_commit(runner, &repo, tree_id, parent.as_ref(), message)?;
+    Ok(Commit::Created)
+}
+
+/// Commit `tree_id` on top of `parent` and advance the current branch,
+/// signing the commit if `commit.gpgsign` is set.
+fn write_commit(runner: &dyn CommandRunner, repo: &Repository, tree_id: git2::Oid, parent: Option<&git2::Commit>, message: &str) -> Result<()> {
+    let signature = repo.signature().map_err(|_| {
+        Error::GitFailed("commit failed: no identity configured, set user.name and user.email in your git config".to_string())
+    })?;
+    let tree = repo.find_tree(tree_id).map_err(failed("commit"))?;
+    let parents: Vec<&git2::Commit> = parent.into_iter().collect();
+
+    let config = repo.config().map_err(failed("commit"))?;
+    if !config.get_bool("commit.gpgsign").unwrap_or(false) {
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .map_err(failed("commit"))?;
+        return Ok(());
+    }
+
+    let buffer = repo.commit_create_buffer(&signature, &signature, message, &tree, &parents)
+        .map_err(failed("commit"))?;
+    let buffer = buffer.as_str()
+        .ok_or_else(|| Error::GitFailed("commit failed: commit is not valid UTF-8".to_string()))?;
+    let gpgsig = sign(runner, &config, buffer)?;
+    let oid = repo.commit_signed(buffer, &gpgsig, None).map_err(failed("commit"))?;
+
+    // commit_signed doesn't move any refs
+    let head = repo.find_reference("HEAD").map_err(failed("commit"))?;
+    match head.symbolic_target() {
+        Some(branch) => {
+            repo.reference(branch, oid, true, message).map_err(failed("commit"))?;
+        },
+        None => repo.set_head_detached(oid).map_err(failed("commit"))?,
+    }
+    Ok(())
+}
+
+/// Sign a commit `buffer` with GPG or SSH, depending on `gpg.format`.
+fn sign(runner: &dyn CommandRunner, config: &git2::Config, buffer: &str) -> Result<String> {
+    let key = config.get_string("user.signingkey").ok();
+    let format = config.get_string("gpg.format").unwrap_or_else(|_| "openpgp".to_string());
+
+    let mut file = tempfile::NamedTempFile::new().context("Failed to create temporary file")?;
+    file.write_all(buffer.as_bytes()).context("Failed to write temporary file")?;
+    let path = file.path().to_string_lossy().into_owned();
+
+    let (cmd, signature_file) = match format.as_str() {
+        "ssh" => {
+            let key = key.ok_or_else(|| {
+                Error::GitFailed("commit signing failed: gpg.format is ssh but user.signingkey is not set".to_string())
+            })?;
+            let program = config.get_string("gpg.ssh.program").unwrap_or_else(|_| "ssh-keygen".to_string());
+
+            // The key is either a path or, prefixed with `key::`, the public key itself
+            let literal = key.strip_prefix("key::").map(str::to_string)
+                .or_else(|| key.starts_with("ssh-").then(|| key.clone()));
+            let mut key_file = None;
+            let key_path = match literal {
+                Some(literal) => {
+                    let mut temp = tempfile::NamedTempFile::new().context("Failed to create temporary file")?;
+                    temp.write_all(literal.as_bytes()).context("Failed to write temporary file")?;
+                    let key_path = temp.path().to_string_lossy().into_owned();
+                    key_file = Some(temp);
+                    key_path
+                },
+                None => shellexpand::tilde(&key).into_owned(),
+            };
+
+            let cmd = Cmd::new(&program).args(["-Y", "sign", "-n", "git", "-f", &key_path, &path]);
+            let output = runner.output(&cmd)?;
+            drop(key_file);
+            (output, Some(format!("{}.sig", path)))
+        },
+        _ => {
+            let program = config.get_string("gpg.program").unwrap_or_else(|_| "gpg".to_string());
+            let mut cmd = Cmd::new(&program).args(["--detach-sign", "--armor"]);
+            if let Some(key) = &key {
+                cmd = cmd.args(["--local-user", key]);
+            }
+            (runner.output(&cmd.args(["--output", "-", &path]))?, None)
+        },
+    };
+
+    if !cmd.success() {
+        return Err(Error::GitFailed(format!("commit signing failed: {}", cmd.stderr.trim())).into());
+    }
+
+    match signature_file {
+        Some(signature_file) => {
+            let signature = fs::read_to_string(&signature_file)
+                .with_context(|| format!("Failed to read {}", signature_file))?;
+            let _ = fs::remove_file(&signature_file);
+            Ok(signature)
+        },
+        None => Ok(cmd.stdout),
+    }
+}
+
+/// Callbacks that authenticate through the SSH agent or the git credential
+/// helper configured in `config`.
+fn remote_callbacks<'a>(config: git2::Config) -> RemoteCallbacks<'a> {
+    let mut attempts = 0;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        // libgit2 keeps asking as long as credentials are rejected
+        attempts += 1;
+        if attempts > 3 {
+            return Err(git2::Error::from_str("authentication failed"));
+        }
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            Cred::credential_helper(&config, url, username)
+        } else {
+            Cred::default()
+        }
+    });
+    callbacks
+}
+
+/// Push `refspec` to the remote `remote_name`.
+fn push_refspec(repo: &Repository, remote_name: &str, refspec: &str) -> Result<()> {
+    let mut remote = repo.find_remote(remote_name).map_err(failed("push"))?;
+
+    let mut rejected = None;
+    let mut callbacks = remote_callbacks(repo.config().map_err(failed("push"))?);
+    callbacks.push_update_reference(|reference, status| {
+        if let Some(status) = status {
+            rejected = Some(format!("{} was rejected: {}", reference, status));
+        }
+        Ok(())
+    });
+
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    remote.push(&[refspec], Some(&mut options))
+        .map_err(failed("push"))?;
+    drop(options);
+
+    match rejected {
+        Some(reason) => Err(Error::GitFailed(format!("push failed: {}", reason)).into()),
+        None => Ok(()),
+    }
+}
+
+/// Push the current branch to its upstream, or to the branch of the same
+/// name on `origin` if it has none.
+pub fn push(repo: &Path) -> Result<()> {
+    let repo = open(repo)?;
+    let head = repo.head().map_err(failed("push"))?;
+    let refname = head.name()
+        .ok_or_else(|| Error::GitFailed("push failed: HEAD is not a valid branch".to_string()))?
+        .to_string();
+    let branch = head.shorthand().unwrap_or_default().to_string();
+
+    let config = repo.config().map_err(failed("push"))?;
+    let remote_name = config.get_string(&format!("branch.{}.remote", branch))
+        .unwrap_or_else(|_| "origin".to_string());
+    let target = config.get_string(&format!("branch.{}.merge", branch))
+        .unwrap_or_else(|_| refname.clone());
+
+    push_refspec(&repo, &remote_name, &format!("{}:{}", refname, target))
+}
+
+/// Push local branch `branch` to a branch of the same name on the remote of
+/// `base`, falling back to `origin`.
+pub fn push_branch(repo: &Path, branch: &str, base: &str) -> Result<()> {
+    let repo = open(repo)?;
+    let remote_name = repo.config().map_err(failed("push"))?
+        .get_string(&format!("branch.{}.remote", base))
+        .unwrap_or_else(|_| "origin".to_string());
+
+    push_refspec(&repo, &remote_name, &format!("refs/heads/{0}:refs/heads/{0}", branch))
+}
+
+/// Name of the branch that is checked out.
+pub fn current_branch(repo: &Path) -> Result<String> {
+    let repo = open(repo)?;
+    let head = repo.head().map_err(failed("branch"))?;
+    if !head.is_branch() {
+        return Err(Error::GitFailed("branch failed: HEAD is detached, check out a branch first".to_string()).into());
+    }
+
+    head.shorthand()
+        .map(str::to_string)
+        .ok_or_else(|| Error::GitFailed("branch failed: branch name is not valid UTF-8".to_string()).into())
+}
+
+/// Turn `name` into a valid branch name that doesn't exist in `repo` yet,
+/// replacing unsupported characters and appending a number if needed.
+pub fn new_branch_name(repo: &Path, name: &str) -> Result<String> {
+    let repo = open(repo)?;
+
+    let mut sanitized = String::new();
+    for c in name.chars() {
+        let c = if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '/' | '-') { c } else { '-' };
+        // Collapse runs of separators, e.g. from "a, b"
+        if !(matches!(c, '-' | '/') && sanitized.ends_with(['-', '/'])) {
+            sanitized.push(c);
+        }
+    }
+    let mut base = sanitized.trim_matches(['-', '/', '.']).replace("..", ".");
+    if base.is_empty() || !git2::Reference::is_valid_name(&format!("refs/heads/{}", base)) {
+        base = "yuki".to_string();
+    }
+
+    // A branch can't be created next to another one that it is a directory
+    // of, or that is a directory of it
+    let branches: Vec<String> = repo.branches(Some(git2::BranchType::Local))
+        .map_err(failed("branch"))?
+        .filter_map(|branch| branch.ok()?.0.name().ok()?.map(str::to_string))
+        .collect();
+    let exists = |name: &str| branches.iter().any(|branch| {
+        branch == name || branch.starts_with(&format!("{}/", name)) || name.starts_with(&format!("{}/", branch))
+    });
+    let mut name = base.clone();
+    let mut n = 2;
+    while exists(&name) {
+        name = format!("{}-{}", base, n);
+        n += 1;
+    }
+    Ok(name)
+}
+
+/// Create branch `name` at the current commit and check it out. The index and
+/// working tree are left as they are.
+pub fn create_branch(repo: &Path, name: &str) -> Result<()> {
+    let repo = open(repo)?;
+    let commit = head_commit(&repo)?
+        .ok_or_else(|| Error::GitFailed("branch failed: the repository has no commits yet".to_string()))?;
+    repo.branch(name, &commit, false).map_err(failed("branch"))?;
+    repo.set_head(&format!("refs/heads/{}", name)).map_err(failed("checkout"))?;
+    Ok(())
+}
+
+/// Check out branch `name`, resetting `files` (absolute or relative to the
+/// repository) to their state on that branch. Other files are left alone.
+pub fn switch_branch(repo: &Path, name: &str, files: &[PathBuf]) -> Result<()> {
+    let repo = open(repo)?;
+    repo.set_head(&format!("refs/heads/{}", name)).map_err(failed("checkout"))?;
+
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    let mut checkout = git2::build::CheckoutBuilder::new();
+    checkout.force();
+    for file in files {
+        let path = if file.is_absolute() { relative(&repo, file)? } else { file.clone() };
+        checkout.path(path);
+    }
+    repo.checkout_head(Some(&mut checkout)).map_err(failed("checkout"))?;
+    Ok(())
+}
+
+/// Delete local branch `name`.
+pub fn delete_branch(repo: &Path, name: &str) -> Result<()> {
+    let repo = open(repo)?;
+    repo.find_branch(name, git2::BranchType::Local)
+        .and_then(|mut branch| branch.delete())
+        .map_err(failed("branch"))
+}
+
+/// The outcome of [`pull`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pull {
+    UpToDate,
+    FastForwarded,
+    Rebased,
+    /// The current branch doesn't track a remote branch
+    NoUpstream,
+}
+
+/// The current branch and its upstream branch, if it has one.
+fn upstream(repo: &Repository) -> Result<Option<(git2::Branch<'_>, git2::Branch<'_>)>> {
+    let head = repo.head().map_err(failed("pull"))?;
+    let Some(name) = head.shorthand().filter(|_| head.is_branch()) else {
+        return Ok(None);
+    };
+
+    let branch = repo.find_branch(name, git2::BranchType::Local).map_err(failed("pull"))?;
+    match branch.upstream() {
+        Ok(upstream) => Ok(Some((branch, upstream))),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Fetch the remote the current branch tracks.
+pub fn fetch(repo: &Path) -> Result<()> {
+    let repo = open(repo)?;
+    let head = repo.head().map_err(failed("fetch"))?;
+    let branch = head.shorthand().unwrap_or_default();
+
+    let Ok(remote_name) = repo.config().map_err(failed("fetch"))?.get_string(&format!("branch.{}.remote", branch)) else {
+        return Ok(());
+    };
+    let mut remote = repo.find_remote(&remote_name).map_err(failed("fetch"))?;
+
+    let mut options = git2::FetchOptions::new();
+    options.remote_callbacks(remote_callbacks(repo.config().map_err(failed("fetch"))?));
+    remote.fetch::<&str>(&[], Some(&mut options), None).map_err(failed("fetch"))?;
+    Ok(())
+}
+
+/// Number of commits the current branch is ahead of and behind its
+/// upstream, as of the last fetch. `None` if it has no upstream.
+pub fn ahead_behind(repo: &Path) -> Result<Option<(usize, usize)>> {
+    let repo = open(repo)?;
+    let Some((branch, upstream)) = upstream(&repo)? else {
+        return Ok(None);
+    };
+
+    let (Some(local), Some(remote)) = (branch.get().target(), upstream.get().target()) else {
+        return Ok(None);
+    };
+    repo.graph_ahead_behind(local, remote)
+        .map(Some)
+        .map_err(failed("status"))
+}
+
+/// Fetch and bring the current branch up to date with its upstream, by
+/// fast-forwarding or, if it has diverged and `rebase` is set, rebasing local
+/// commits on top. Nothing is changed if local modifications or rebase
+/// conflicts get in the way.
+pub fn pull(repo_path: &Path, rebase: bool) -> Result<Pull> {
+    fetch(repo_path)?;
+
+    let repo = open(repo_path)?;
+    let Some((branch, upstream)) = upstream(&repo)? else {
+        return Ok(Pull::NoUpstream);
+    };
+    let local = branch.get().target()
+        .ok_or_else(|| Error::GitFailed("pull failed: current branch has no commit".to_string()))?;
+    let remote = upstream.get().target()
+        .ok_or_else(|| Error::GitFailed("pull failed: upstream branch has no commit".to_string()))?;
+
+    let (ahead, behind) = repo.graph_ahead_behind(local, remote).map_err(failed("pull"))?;
+    if behind == 0 {
+        return Ok(Pull::UpToDate);
+    }
+
+    if ahead == 0 {
+        // A safe checkout refuses to overwrite local modifications
+        let target = repo.find_object(remote, None).map_err(failed("pull"))?;
+        repo.checkout_tree(&target, Some(git2::build::CheckoutBuilder::new().safe()))
+            .map_err(failed("pull"))?;
+        let mut reference = branch.into_reference();
+        reference.set_target(remote, "yuki: fast-forward").map_err(failed("pull"))?;
+        return Ok(Pull::FastForwarded);
+    }
+
+    if !rebase {
+        return Err(Error::GitFailed(format!(
+            "pull failed: the branch has diverged from its upstream ({} local, {} remote commits), set pull_rebase = true or merge them yourself",
+            ahead, behind
+        )).into());
+    }
+
+    let signature = repo.signature().map_err(|_| {
+        Error::GitFailed("pull failed: no identity configured, set user.name and user.email in your git config".to_string())
+    })?;
+    let local = repo.reference_to_annotated_commit(branch.get()).map_err(failed("pull"))?;
+    let onto = repo.reference_to_annotated_commit(upstream.get()).map_err(failed("pull"))?;
+
+    let mut rebase = repo.rebase(Some(&local), Some(&onto), None, None).map_err(failed("pull"))?;
+    while let Some(operation) = rebase.next() {
+        let conflicted = operation.is_err()
+            || repo.index().map_err(failed("pull"))?.has_conflicts();
+        if conflicted {
+            let _ = rebase.abort();
+            return Err(Error::GitFailed(
+                "pull failed: rebasing local commits onto the upstream conflicts, nothing was changed; resolve it with git pull --rebase".to_string()
+            ).into());
+        }
+
+        if let Err(e) = rebase.commit(None, &signature, None) {
+            // The commit is empty if it's already upstream
+            if e.code() != git2::ErrorCode::Applied {
+                let _ = rebase.abort();
+                return Err(failed("pull")(e));
+            }
+        }
+    }
+    rebase.finish(Some(&signature)).map_err(failed("pull"))?;
+
+    Ok(Pull::Rebased)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::runner::FakeRunner;
+
+    /// A repository at `path` with an identity and `apps.nix` in its first
+    /// commit on `main`.
+    fn init(path: &Path) -> Repository {
+        let repo = Repository::init_opts(path, git2::RepositoryInitOptions::new().initial_head("main")).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "yuki").unwrap();
+        config.set_str("user.email", "yuki@example.com").unwrap();
+        config.set_bool("commit.gpgsign", false).unwrap();
+        fs::write(path.join("apps.nix"), "[ ]\n").unwrap();
+        commit_files(&FakeRunner::new(), path, &[path.join("apps.nix")], "init").unwrap();
+        repo
+    }
+
+    #[test]
+    fn sanitizes_and_numbers_branch_names() {
+        let dir = tempfile::tempdir().unwrap();
+        init(dir.path());
+
+        assert_eq!(new_branch_name(dir.path(), "yuki/web/ripgrep, fd").unwrap(), "yuki/web/ripgrep-fd");
+        assert_eq!(new_branch_name(dir.path(), "--a..b~^:c--").unwrap(), "a.b-c");
+        assert_eq!(new_branch_name(dir.path(), "/// ").unwrap(), "yuki");
+
+        create_branch(dir.path(), "yuki/web/ripgrep").unwrap();
+        assert_eq!(new_branch_name(dir.path(), "yuki/web/ripgrep").unwrap(), "yuki/web/ripgrep-2");
+        // "yuki/web" can't exist next to "yuki/web/ripgrep"
+        assert_eq!(new_branch_name(dir.path(), "yuki/web").unwrap(), "yuki/web-2");
+    }
+
+    #[test]
+    fn follows_symlinked_package_file_into_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("dotfiles");
+        fs::create_dir(&repo).unwrap();
+        init(&repo);
+        let link = dir.path().join("apps.nix");
+        std::os::unix::fs::symlink(repo.join("apps.nix"), &link).unwrap();
+
+        fs::write(&link, "[ git ]\n").unwrap();
+        assert_eq!(changed_files(&repo, std::slice::from_ref(&link)).unwrap(), [PathBuf::from("apps.nix")]);
+        assert!(ensure_clean(&repo, std::slice::from_ref(&link)).is_err());
+
+        assert_eq!(commit_files(&FakeRunner::new(), &repo, std::slice::from_ref(&link), "add git").unwrap(), Commit::Created);
+        assert!(changed_files(&repo, &[]).unwrap().is_empty());
+    }
+
+    /// Write `content` to `file` in `repo` and commit it.
+    fn commit_change(repo: &Path, file: &str, content: &str) -> git2::Oid {
+        fs::write(repo.join(file), content).unwrap();
+        commit_files(&FakeRunner::new(), repo, &[repo.join(file)], &format!("change {}", file)).unwrap();
+        head(repo)
+    }
+
+    fn head(repo: &Path) -> git2::Oid {
+        Repository::open(repo).unwrap().head().unwrap().target().unwrap()
+    }
+
+    /// `dotfiles` tracking `main` of a bare `remote.git`, and a second
+    /// clone `other` to push upstream changes from.
+    fn repos_with_remote(dir: &Path) -> (PathBuf, PathBuf) {
+        let (repo, remote, other) = (dir.join("dotfiles"), dir.join("remote.git"), dir.join("other"));
+        Repository::init_opts(&remote, git2::RepositoryInitOptions::new().bare(true).initial_head("main")).unwrap();
+        let git = init(&repo);
+        git.remote("origin", remote.to_str().unwrap()).unwrap();
+        let mut config = git.config().unwrap();
+        config.set_str("branch.main.remote", "origin").unwrap();
+        config.set_str("branch.main.merge", "refs/heads/main").unwrap();
+        push(&repo).unwrap();
+
+        let clone = Repository::clone(remote.to_str().unwrap(), &other).unwrap();
+        let mut config = clone.config().unwrap();
+        config.set_str("user.name", "other").unwrap();
+        config.set_str("user.email", "other@example.com").unwrap();
+        (repo, other)
+    }
+
+    #[test]
+    fn pull_fast_forwards_when_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, other) = repos_with_remote(dir.path());
+        let upstream = commit_change(&other, "apps.nix", "[ git ]\n");
+        push(&other).unwrap();
+
+        assert_eq!(pull(&repo, false).unwrap(), Pull::FastForwarded);
+        assert_eq!(head(&repo), upstream);
+        assert_eq!(fs::read_to_string(repo.join("apps.nix")).unwrap(), "[ git ]\n");
+        assert_eq!(pull(&repo, false).unwrap(), Pull::UpToDate);
+    }
+
+    #[test]
+    fn pull_refuses_diverged_branch_without_rebase() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, other) = repos_with_remote(dir.path());
+        commit_change(&other, "apps.nix", "[ git ]\n");
+        push(&other).unwrap();
+        let local = commit_change(&repo, "notes.nix", "[ ]\n");
+
+        let e = pull(&repo, false).unwrap_err();
+
+        assert_eq!(Error::exit_code_of(&e), 10);
+        assert_eq!(head(&repo), local);
+        assert_eq!(fs::read_to_string(repo.join("apps.nix")).unwrap(), "[ ]\n");
+    }
+
+    #[test]
+    fn pull_rebases_diverged_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, other) = repos_with_remote(dir.path());
+        let upstream = commit_change(&other, "apps.nix", "[ git ]\n");
+        push(&other).unwrap();
+        commit_change(&repo, "notes.nix", "[ ]\n");
+
+        assert_eq!(pull(&repo, true).unwrap(), Pull::Rebased);
+
+        let git = Repository::open(&repo).unwrap();
+        let commit = git.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(commit.summary(), Some("change notes.nix"));
+        assert_eq!(commit.parent_id(0).unwrap(), upstream);
+        assert_eq!(fs::read_to_string(repo.join("apps.nix")).unwrap(), "[ git ]\n");
+        assert!(changed_files(&repo, &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn pull_aborts_conflicting_rebase() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, other) = repos_with_remote(dir.path());
+        commit_change(&other, "apps.nix", "[ git ]\n");
+        push(&other).unwrap();
+        let local = commit_change(&repo, "apps.nix", "[ vim ]\n");
+
+        let e = pull(&repo, true).unwrap_err();
+
+        assert_eq!(Error::exit_code_of(&e), 10);
+        assert_eq!(head(&repo), local);
+        assert_eq!(Repository::open(&repo).unwrap().state(), git2::RepositoryState::Clean);
+        assert_eq!(fs::read_to_string(repo.join("apps.nix")).unwrap(), "[ vim ]\n");
+        assert!(changed_files(&repo, &[]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,27 @@
+//! yuki manages the packages declared in your Nix (and nix-darwin Homebrew)
+//! configuration files. The `yuki` binary is a thin front-end over this crate.
+
+pub mod backend;
+pub mod backup;
+pub mod config;
+pub mod diff;
+pub mod error;
+pub mod flake;
+pub mod git;
+pub mod lock;
+pub mod message;
+pub mod packages;
+pub mod platform;
+pub mod runner;
+
+pub use backend::{Package, PackageBackend};
+pub use config::Config;
+pub use error::Error;
+pub use message::MessageContext;
+pub use packages::{Edit, PackageList};
+pub use platform::Platform;
+pub use runner::{CommandRunner, SystemRunner};
+
+/// Result type used throughout yuki. Failures carry an [`Error`] where the
+/// cause is known.
+pub type Result<T> = anyhow::Result<T>;
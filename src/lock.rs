@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::io::AsRawFd,
+    thread,
+    time::{Duration, Instant},
+};
+use crate::config::Config;
+use crate::error::Error;
+
+/// How often a waiting process checks whether the lock was released.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Exclusive lock that yuki holds from reading a package file until the
+/// commit and hook command are done, so concurrent runs can't overwrite each
+/// other's edits. Released when dropped.
+#[derive(Debug)]
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Take the lock, waiting up to `timeout` for another yuki process that
+    /// holds it. `on_wait` is called with the pid of that process once it's
+    /// clear that yuki has to wait.
+    pub fn acquire(timeout: Duration, on_wait: impl FnOnce(&str)) -> Result<Self> {
+        let path = Config::get_state_dir()?.join("lock");
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        let start = Instant::now();
+        let mut on_wait = Some(on_wait);
+        let mut holder = None;
+        loop {
+            // SAFETY: the descriptor belongs to `file`, which outlives the call
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+                break;
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::WouldBlock {
+                return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+            }
+
+            // The holder writes its pid after taking the lock
+            let pid = holder.get_or_insert_with(|| {
+                let pid = fs::read_to_string(&path).unwrap_or_default().trim().to_string();
+                if pid.is_empty() { "unknown".to_string() } else { pid }
+            });
+            if let Some(on_wait) = on_wait.take() {
+                on_wait(pid);
+            }
+            if start.elapsed() >= timeout {
+                return Err(Error::Locked {
+                    pid: pid.clone(),
+                    seconds: timeout.as_secs(),
+                }.into());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        file.set_len(0)
+            .and_then(|_| write!(file, "{}", std::process::id()))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(Self { _file: file })
+    }
+}
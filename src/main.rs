@@ -1,7 +1,10 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use clap::{Parser, Subcommand};
-use commands::{check_doctor, install_package, list_packages, search_packages, uninstall_package, update_packages};
-mod config;
+use commands::{check_doctor, run_config, ConfigCommand, install_packages, list_packages, restore_backup, search_packages, uninstall_packages, update_packages};
+use yuki::runner::SystemRunner;
+use std::path::PathBuf;
+use yuki::config::Overrides;
+use yuki::{Config, Error, Platform};
 mod commands;
 
 
@@ -10,6 +13,42 @@ mod commands;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Platform whose package files to manage (defaults to the current system)
+    #[arg(long, global = true, value_enum)]
+    platform: Option<Platform>,
+
+    /// Config file to use instead of ~/.config/yuki/config.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Nix packages file to edit instead of the configured one
+    #[arg(long, global = true, value_name = "PATH")]
+    packages_file: Option<PathBuf>,
+
+    /// Don't commit changes, regardless of auto_commit
+    #[arg(long, global = true)]
+    no_commit: bool,
+
+    /// Don't push commits, regardless of auto_push
+    #[arg(long, global = true)]
+    no_push: bool,
+
+    /// Don't run the install, uninstall or update command
+    #[arg(long, global = true)]
+    no_hook: bool,
+
+    /// Don't check that packages exist before adding them
+    #[arg(long, global = true)]
+    no_verify: bool,
+
+    /// Keep and commit a change even if the install or uninstall command fails
+    #[arg(long, global = true)]
+    keep_on_failure: bool,
+
+    /// Show the changes and the commands that would run, without making them
+    #[arg(long, global = true)]
+    dry_run: bool,
 }
 
 #[derive(Subcommand)]
@@ -19,76 +58,104 @@ enum Commands {
         /// Package name to search for
         query: String,
     },
-    /// Install a package
+    /// Install one or more packages
     Install {
-        /// Package name to install
-        package: String,
+        /// Package names to install
+        #[arg(required = true)]
+        packages: Vec<String>,
+        /// Host profile whose package files to edit
+        #[arg(long)]
+        host: Option<String>,
     },
     /// List installed packages
-    List,
-    /// Uninstall a package
+    List {
+        /// Host profile whose packages to list
+        #[arg(long)]
+        host: Option<String>,
+    },
+    /// Uninstall one or more packages
     Uninstall {
-        /// Package name to remove
-        package: String,
+        /// Package names to remove
+        #[arg(required = true)]
+        packages: Vec<String>,
+        /// Host profile whose package files to edit
+        #[arg(long)]
+        host: Option<String>,
     },
     /// Update all packages
-    Update,
+    Update {
+        /// Host profile to update
+        #[arg(long)]
+        host: Option<String>,
+    },
+    /// Roll a package file back to a backup made before a change
+    Restore {
+        /// Backup to restore, defaults to the newest one
+        backup: Option<String>,
+        /// List the backups of each package file
+        #[arg(long, conflicts_with = "backup")]
+        list: bool,
+        /// Host profile whose package files to restore
+        #[arg(long)]
+        host: Option<String>,
+    },
     /// Check system configuration and dependencies
     Doctor,
+    /// Show or change settings
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
 }
 
 
-fn run_command(cmd: &str) -> Result<String> {
-    let output = std::process::Command::new("sh")
-        .arg("-c")
-        .arg(cmd)
-        .output()
-        .with_context(|| format!("Failed to execute command: {}", cmd))?;
-
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
-    } else {
-        Err(anyhow::anyhow!(
-            "Command failed: {}\nError: {}",
-            cmd,
-            String::from_utf8_lossy(&output.stderr)
-        ))
+fn run(cli: Cli) -> Result<()> {
+    let runner = SystemRunner;
+
+    // Handled before loading the config so a broken config can still be fixed
+    if let Commands::Config { action } = cli.command {
+        return run_config(&runner, cli.config.as_deref(), action);
     }
-}
 
+    let host = match &cli.command {
+        Commands::Install { host, .. }
+        | Commands::List { host }
+        | Commands::Uninstall { host, .. }
+        | Commands::Update { host }
+        | Commands::Restore { host, .. } => host.clone(),
+        _ => None,
+    };
 
-fn check_dependencies() -> Result<()> {
-    // Check for required dependencies
-    let dependencies = ["git"];
-    
-    for dep in dependencies {
-        if run_command(&format!("which {}", dep)).is_err() {
-            return Err(anyhow::anyhow!(
-                "Required dependency '{}' not found. Please install it first.", 
-                dep
-            ));
-        }
+    let config = Config::load_with(&Overrides {
+        config_path: cli.config,
+        host,
+        platform: cli.platform,
+        packages_file: cli.packages_file,
+        no_commit: cli.no_commit,
+        no_push: cli.no_push,
+        no_hook: cli.no_hook,
+        no_verify: cli.no_verify,
+        keep_on_failure: cli.keep_on_failure,
+        dry_run: cli.dry_run,
+    })?;
+
+    match cli.command {
+        Commands::Search { query } => search_packages(&config, &runner, &query),
+        Commands::Install { packages, .. } => install_packages(&config, &runner, &packages),
+        Commands::List { .. } => list_packages(&config, &runner),
+        Commands::Uninstall { packages, .. } => uninstall_packages(&config, &runner, &packages),
+        Commands::Update { .. } => update_packages(&config, &runner),
+        Commands::Restore { backup, list, .. } => restore_backup(&config, &runner, list, backup.as_deref()),
+        Commands::Doctor => check_doctor(&config, &runner),
+        Commands::Config { .. } => unreachable!(),
     }
-    
-    Ok(())
 }
 
-
-
-fn main() -> Result<()> {
+fn main() {
     let cli = Cli::parse();
-    
-    // Check dependencies before proceeding
-    check_dependencies()?;
-    
-    let config = config::Config::load()?;
 
-    match cli.command {
-        Commands::Search { query } => search_packages(&config, &query),
-        Commands::Install { package } => install_package(&config, &package, None),
-        Commands::List => list_packages(&config),
-        Commands::Uninstall { package } => uninstall_package(&config, &package),
-        Commands::Update => update_packages(&config),
-        Commands::Doctor => check_doctor(&config),
+    if let Err(e) = run(cli) {
+        eprintln!("❌ {:#}", e);
+        std::process::exit(Error::exit_code_of(&e));
     }
 }
@@ -0,0 +1,90 @@
+//! Commit message templates.
+//!
+//! Templates are plain text with placeholders in angle brackets:
+//!
+//! | Placeholder  | Value                                                   |
+//! |--------------|---------------------------------------------------------|
+//! | `<package>`  | The package, or all packages separated by commas        |
+//! | `<packages>` | All packages, separated by commas                       |
+//! | `<source>`   | Id of the package source (`nixpkgs`, `brew` or `cask`)  |
+//! | `<host>`     | Host profile in use, or the hostname                    |
+//! | `<file>`     | Edited package file, relative to the repository         |
+//! | `<version>`  | Version of the installed package, if known              |
+//! | `<inputs>`   | Flake inputs changed by an update, one per line         |
+//!
+//! Placeholders without a value are replaced by an empty string, unknown ones
+//! are left alone.
+
+use crate::flake::InputChange;
+
+/// Values for the placeholders of a commit message template.
+#[derive(Debug, Clone, Default)]
+pub struct MessageContext {
+    pub packages: Vec<String>,
+    pub source: String,
+    pub host: String,
+    pub file: String,
+    pub version: String,
+    pub inputs: Vec<InputChange>,
+}
+
+impl MessageContext {
+    /// Fill the placeholders of `template`.
+    pub fn render(&self, template: &str) -> String {
+        let packages = self.packages.join(", ");
+        let inputs = self.inputs.iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let values = [
+            ("<package>", packages.as_str()),
+            ("<packages>", packages.as_str()),
+            ("<source>", self.source.as_str()),
+            ("<host>", self.host.as_str()),
+            ("<file>", self.file.as_str()),
+            ("<version>", self.version.as_str()),
+            ("<inputs>", inputs.as_str()),
+        ];
+
+        let message = values.iter()
+            .fold(template.to_string(), |message, (placeholder, value)| message.replace(placeholder, value));
+        message.trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_placeholders() {
+        let context = MessageContext {
+            packages: vec!["ripgrep".to_string(), "fd".to_string()],
+            source: "nixpkgs".to_string(),
+            host: "web".to_string(),
+            file: "hosts/web/apps.nix".to_string(),
+            ..MessageContext::default()
+        };
+        assert_eq!(
+            context.render("feat(<host>): add <packages> from <source> to <file> <version>"),
+            "feat(web): add ripgrep, fd from nixpkgs to hosts/web/apps.nix"
+        );
+        assert_eq!(context.render("installed <package> <unknown>"), "installed ripgrep, fd <unknown>");
+    }
+
+    #[test]
+    fn renders_inputs_one_per_line() {
+        let context = MessageContext {
+            inputs: vec![
+                InputChange { name: "home-manager".to_string(), old: None, new: Some("0123456789".to_string()) },
+                InputChange { name: "nixpkgs".to_string(), old: Some("aaaaaaaaaa".to_string()), new: Some("bbbbbbbbbb".to_string()) },
+            ],
+            ..MessageContext::default()
+        };
+        assert_eq!(
+            context.render("chore: update\n\n<inputs>\n"),
+            "chore: update\n\nhome-manager: added at 0123456\nnixpkgs: aaaaaaa → bbbbbbb"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use crate::backend::{self, Package, PackageBackend};
+use crate::config::Config;
+use crate::error::Error;
+use crate::lock::Lock;
+use crate::runner::CommandRunner;
+
+/// Packages declared for one backend.
+#[derive(Debug)]
+pub struct PackageList {
+    /// Id of the backend
+    pub source: &'static str,
+    pub description: &'static str,
+    pub file: PathBuf,
+    /// The package names, or why the package file couldn't be read
+    pub packages: Result<Vec<String>>,
+}
+
+/// The outcome of adding or removing a package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    /// Id of the backend whose file was edited
+    pub source: &'static str,
+    pub package: String,
+    pub file: PathBuf,
+    /// `false` if the file already was in the requested state
+    pub changed: bool,
+}
+
+fn backend_for<'a, 'r>(backends: &'a [Box<dyn PackageBackend + 'r>], source: &str) -> Result<&'a (dyn PackageBackend + 'r)> {
+    backend::find(backends, source)
+        .ok_or_else(|| Error::BackendUnavailable {
+            name: source.to_string(),
+            reason: "unknown package source".to_string(),
+        }.into())
+}
+
+/// Packages declared in the package files of every registered backend.
+pub fn list(config: &Config, runner: &dyn CommandRunner) -> Result<Vec<PackageList>> {
+    Ok(backend::registered(config, runner)?
+        .iter()
+        .map(|backend| PackageList {
+            source: backend.id(),
+            description: backend.describe(),
+            file: backend.list_file().path().to_path_buf(),
+            packages: backend.list(),
+        })
+        .collect())
+}
+
+/// Search every registered backend for `query`, sorted by name. Backends that
+/// fail to search are skipped unless nothing was found at all.
+pub fn search(config: &Config, runner: &dyn CommandRunner, query: &str) -> Result<Vec<Package>> {
+    let mut packages = Vec::new();
+    let mut error = None;
+    for backend in backend::registered(config, runner)? {
+        match backend.search(query) {
+            Ok(found) => packages.extend(found),
+            Err(e) => {
+                error.get_or_insert(e.context(format!("Failed to search {}", backend.describe())));
+            }
+        }
+    }
+
+    if let (true, Some(e)) = (packages.is_empty(), error) {
+        return Err(e);
+    }
+
+    packages.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.attr.cmp(&b.attr)));
+    Ok(packages)
+}
+
+/// Ids of the backends whose package files contain `package`.
+pub fn sources_of(config: &Config, runner: &dyn CommandRunner, package: &str) -> Result<Vec<&'static str>> {
+    Ok(backend::registered(config, runner)?
+        .iter()
+        .filter(|backend| backend.contains(package).unwrap_or(false))
+        .map(|backend| backend.id())
+        .collect())
+}
+
+/// The package file the backend with id `source` edits.
+pub fn file_of(config: &Config, runner: &dyn CommandRunner, source: &str) -> Result<PathBuf> {
+    let backends = backend::registered(config, runner)?;
+    Ok(backend_for(&backends, source)?.list_file().path().to_path_buf())
+}
+
+/// Check that `package` exists in the backend with id `source`.
+pub fn verify(config: &Config, runner: &dyn CommandRunner, source: &str, package: &str) -> Result<()> {
+    let backends = backend::registered(config, runner)?;
+    backend_for(&backends, source)?.verify(package)
+}
+
+/// Add `package` to the package file of the backend with id `source`,
+/// holding the [`Lock`] while the file is edited.
+pub fn add(config: &Config, runner: &dyn CommandRunner, source: &str, package: &str) -> Result<Edit> {
+    let backends = backend::registered(config, runner)?;
+    let backend = backend_for(&backends, source)?;
+    let _lock = Lock::acquire(config.lock_timeout(), |_| {})?;
+
+    Ok(Edit {
+        source: backend.id(),
+        package: package.to_string(),
+        file: backend.list_file().path().to_path_buf(),
+        changed: backend.add(package)?,
+    })
+}
+
+/// Current contents of the package file of the backend with id `source`.
+pub fn contents_of(config: &Config, runner: &dyn CommandRunner, source: &str) -> Result<String> {
+    let backends = backend::registered(config, runner)?;
+    backend_for(&backends, source)?.list_file().read()
+}
+
+/// Like [`add`], but applied to `content` instead of the file and returning
+/// the new content instead of writing it.
+pub fn preview_add(config: &Config, runner: &dyn CommandRunner, source: &str, content: &str, package: &str) -> Result<(Edit, String)> {
+    let backends = backend::registered(config, runner)?;
+    let backend = backend_for(&backends, source)?;
+    let new_content = backend.list_file().with_added(content, package)?;
+
+    Ok((Edit {
+        source: backend.id(),
+        package: package.to_string(),
+        file: backend.list_file().path().to_path_buf(),
+        changed: new_content.is_some(),
+    }, new_content.unwrap_or_else(|| content.to_string())))
+}
+
+/// Like [`remove`], but applied to `content` instead of the file and
+/// returning the new content instead of writing it.
+pub fn preview_remove(config: &Config, runner: &dyn CommandRunner, source: &str, content: &str, package: &str) -> Result<(Edit, String)> {
+    let backends = backend::registered(config, runner)?;
+    let backend = backend_for(&backends, source)?;
+    let new_content = backend.list_file().with_removed(content, package)?;
+
+    Ok((Edit {
+        source: backend.id(),
+        package: package.to_string(),
+        file: backend.list_file().path().to_path_buf(),
+        changed: new_content.is_some(),
+    }, new_content.unwrap_or_else(|| content.to_string())))
+}
+
+/// Remove `package` from the package file of the backend with id `source`,
+/// holding the [`Lock`] while the file is edited.
+pub fn remove(config: &Config, runner: &dyn CommandRunner, source: &str, package: &str) -> Result<Edit> {
+    let backends = backend::registered(config, runner)?;
+    let backend = backend_for(&backends, source)?;
+    let _lock = Lock::acquire(config.lock_timeout(), |_| {})?;
+
+    Ok(Edit {
+        source: backend.id(),
+        package: package.to_string(),
+        file: backend.list_file().path().to_path_buf(),
+        changed: backend.remove(package)?,
+    })
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// The kind of system whose package files are being managed. This is usually
+/// the system yuki runs on, but can be overridden to edit another host's files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase", try_from = "String")]
+pub enum Platform {
+    Linux,
+    Darwin,
+}
+
+impl Platform {
+    /// The platform yuki is currently running on.
+    pub fn detect() -> Self {
+        match std::env::consts::OS {
+            "macos" => Platform::Darwin,
+            _ => Platform::Linux,
+        }
+    }
+
+    /// Whether Homebrew packages are managed on this platform.
+    pub fn has_homebrew(self) -> bool {
+        self == Platform::Darwin
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Platform::Linux => write!(f, "linux"),
+            Platform::Darwin => write!(f, "darwin"),
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "linux" => Ok(Platform::Linux),
+            "darwin" | "macos" => Ok(Platform::Darwin),
+            "auto" => Ok(Platform::detect()),
+            other => Err(anyhow::anyhow!("Unknown platform '{}', expected 'darwin' or 'linux'", other)),
+        }
+    }
+}
+
+impl TryFrom<String> for Platform {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
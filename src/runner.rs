@@ -0,0 +1,287 @@
+use anyhow::{Context, Result};
+use colored::*;
+use std::{
+    fmt,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+/// An external command to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cmd {
+    pub program: String,
+    pub args: Vec<String>,
+    pub dir: Option<PathBuf>,
+}
+
+impl Cmd {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            args: Vec::new(),
+            dir: None,
+        }
+    }
+
+    /// Run `command` through `sh -c`.
+    pub fn shell(command: &str) -> Self {
+        Self::new("sh").args(["-c", command])
+    }
+
+    pub fn arg(mut self, arg: impl AsRef<str>) -> Self {
+        self.args.push(arg.as_ref().to_string());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args.extend(args.into_iter().map(|a| a.as_ref().to_string()));
+        self
+    }
+
+    pub fn current_dir(mut self, dir: &Path) -> Self {
+        self.dir = Some(dir.to_path_buf());
+        self
+    }
+
+    fn to_command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+}
+
+impl fmt::Display for Cmd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Exit status and captured output of a finished command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    /// Exit code, `-1` if the process was terminated by a signal
+    pub status: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Output {
+    /// A successful run that printed `stdout`.
+    pub fn ok(stdout: &str) -> Self {
+        Self {
+            status: 0,
+            stdout: stdout.to_string(),
+            stderr: String::new(),
+        }
+    }
+
+    /// A failed run with exit code `status` that printed `stderr`.
+    pub fn err(status: i32, stderr: &str) -> Self {
+        Self {
+            status,
+            stdout: String::new(),
+            stderr: stderr.to_string(),
+        }
+    }
+
+    pub fn success(&self) -> bool {
+        self.status == 0
+    }
+}
+
+/// Runs external commands. Everything yuki executes goes through this so it
+/// can be swapped out, e.g. for [`FakeRunner`] in tests.
+pub trait CommandRunner {
+    /// Run `cmd` to completion and capture its output. Only fails if the
+    /// command could not be started.
+    fn output(&self, cmd: &Cmd) -> Result<Output>;
+
+    /// Run `cmd` while forwarding its output to the terminal. The returned
+    /// output only carries the exit status.
+    fn stream(&self, cmd: &Cmd) -> Result<Output>;
+
+    /// Run `cmd` attached to the terminal, e.g. to open an editor. The
+    /// returned output only carries the exit status.
+    fn interactive(&self, cmd: &Cmd) -> Result<Output>;
+
+    /// Whether `program` is available on the `PATH`.
+    fn exists(&self, program: &str) -> bool {
+        self.output(&Cmd::new("which").arg(program))
+            .map(|output| output.success())
+            .unwrap_or(false)
+    }
+}
+
+/// Runs commands on the host system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn output(&self, cmd: &Cmd) -> Result<Output> {
+        let output = cmd.to_command()
+            .output()
+            .with_context(|| format!("Failed to execute command: {}", cmd))?;
+
+        Ok(Output {
+            status: output.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+
+    fn stream(&self, cmd: &Cmd) -> Result<Output> {
+        // Create command with piped output
+        let mut child = cmd.to_command()
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to spawn command: {}", cmd))?;
+
+        // Handle stdout in real-time
+        if let Some(stdout) = child.stdout.take() {
+            let stdout_reader = BufReader::new(stdout);
+            for line in stdout_reader.lines().map_while(Result::ok) {
+                println!("{}", line);
+            }
+        }
+
+        // Handle stderr in real-time
+        if let Some(stderr) = child.stderr.take() {
+            let stderr_reader = BufReader::new(stderr);
+            for line in stderr_reader.lines().map_while(Result::ok) {
+                eprintln!("{}", line.red());
+            }
+        }
+
+        let status = child.wait()
+            .with_context(|| format!("Failed to execute command: {}", cmd))?;
+
+        Ok(Output {
+            status: status.code().unwrap_or(-1),
+            ..Output::default()
+        })
+    }
+
+    fn interactive(&self, cmd: &Cmd) -> Result<Output> {
+        let status = cmd.to_command()
+            .status()
+            .with_context(|| format!("Failed to execute command: {}", cmd))?;
+
+        Ok(Output {
+            status: status.code().unwrap_or(-1),
+            ..Output::default()
+        })
+    }
+}
+
+/// A [`CommandRunner`] that never executes anything. It records every command
+/// it is asked to run and replies with canned output registered via
+/// [`FakeRunner::on`]; commands without a canned reply succeed with no output.
+#[derive(Debug, Default)]
+pub struct FakeRunner {
+    replies: Mutex<Vec<(String, Output)>>,
+    calls: Mutex<Vec<Cmd>>,
+}
+
+impl FakeRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reply with `output` to every command whose command line starts with
+    /// `prefix`. Later registrations take precedence over earlier ones.
+    pub fn on(&self, prefix: &str, output: Output) -> &Self {
+        self.replies.lock().unwrap().push((prefix.to_string(), output));
+        self
+    }
+
+    /// All commands run so far, in order.
+    pub fn calls(&self) -> Vec<Cmd> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Command lines of all commands run so far, in order.
+    pub fn command_lines(&self) -> Vec<String> {
+        self.calls().iter().map(Cmd::to_string).collect()
+    }
+
+    fn reply(&self, cmd: &Cmd) -> Output {
+        self.calls.lock().unwrap().push(cmd.clone());
+
+        let line = cmd.to_string();
+        self.replies.lock().unwrap()
+            .iter()
+            .rev()
+            .find(|(prefix, _)| line.starts_with(prefix.as_str()))
+            .map(|(_, output)| output.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl CommandRunner for FakeRunner {
+    fn output(&self, cmd: &Cmd) -> Result<Output> {
+        Ok(self.reply(cmd))
+    }
+
+    fn stream(&self, cmd: &Cmd) -> Result<Output> {
+        let output = self.reply(cmd);
+        Ok(Output {
+            status: output.status,
+            ..Output::default()
+        })
+    }
+
+    fn interactive(&self, cmd: &Cmd) -> Result<Output> {
+        self.stream(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fake_runner_records_commands() {
+        let runner = FakeRunner::new();
+        runner.output(&Cmd::new("git").args(["status", "--porcelain"])).unwrap();
+        runner.stream(&Cmd::shell("make").current_dir(Path::new("/dotfiles"))).unwrap();
+
+        assert_eq!(runner.command_lines(), ["git status --porcelain", "sh -c make"]);
+        assert_eq!(runner.calls()[1].dir.as_deref(), Some(Path::new("/dotfiles")));
+    }
+
+    #[test]
+    fn fake_runner_replies_by_prefix() {
+        let runner = FakeRunner::new();
+        runner.on("nix search", Output::ok("{}"))
+            .on("nix search nixpkgs missing", Output::err(1, "no results"));
+
+        assert_eq!(runner.output(&Cmd::new("nix").args(["search", "nixpkgs", "git"])).unwrap(), Output::ok("{}"));
+        assert_eq!(runner.output(&Cmd::new("nix").args(["search", "nixpkgs", "missing"])).unwrap(), Output::err(1, "no results"));
+        // Unknown commands succeed without output
+        assert_eq!(runner.output(&Cmd::new("brew").arg("info")).unwrap(), Output::default());
+    }
+
+    #[test]
+    fn fake_runner_streams_only_the_status() {
+        let runner = FakeRunner::new();
+        runner.on("sh -c", Output { status: 2, stdout: "built".to_string(), stderr: "failed".to_string() });
+
+        let output = runner.stream(&Cmd::shell("make")).unwrap();
+        assert_eq!(output, Output { status: 2, ..Output::default() });
+        assert!(!output.success());
+    }
+}